@@ -1,2 +0,0 @@
-#!/bin/bash
-cat error.json | cut -c -$(cat error_column) | awk -F, '{print $NF}'
@@ -1,447 +1,805 @@
-use serde::Deserialize;
-use crate::smartzone::*;
+//! Access points as returned by `query/ap`.
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::api::*;
+use crate::version::ApiVersion;
+
+/// 6 GHz fields that controllers before v11_0 leave out of the response.
+const SIX_GHZ_FIELDS: &[&str] = &[
+    "noise6G", "airtime6G", "latency6G", "capacity6G", "channel6G", "channel6gValue",
+    "retry6G", "numClients6G", "tx6G", "rx6G", "txRx6G", "wlanGroup6gId", "wlanGroup6gName",
+    "isLatency6GFlagged", "isCapacity6GFlagged", "isConnectionFailure6GFlagged",
+    "isAirtimeUtilization6GFlagged", "eirp6G", "medianTxRadioMCSRate6G",
+    "medianRxRadioMCSRate6G", "txPowerOffset6G", "rxDesense6G", "cumulativeTx6G",
+    "cumulativeRx6G", "cumulativeTxRx6G",
+];
+
+/// An access point as the controller sees it.
+///
+/// Per band fields come in threes, 2.4, 5 and 6 GHz, and are gathered up by
+/// [`AP::radios`]. Traffic counters without `cumulative` in their name cover
+/// the controller's last sampling interval, not the AP's lifetime.
+#[derive(Deserialize, Serialize, Default)]
 pub struct AP {
+    /// Name of the AP, its MAC unless one was set.
     #[serde(rename = "deviceName")]
     pub device_name: String,
+    /// Free text description set in the controller.
     #[serde(rename = "description")]
     pub description: String,
+    /// Connection status, `Online` when the controller hears from the AP.
     #[serde(rename = "status")]
     pub status: String,
+    /// Number of open alerts.
     #[serde(rename = "alerts")]
     pub alerts: u64,
+    /// IPv4 address.
     #[serde(rename = "ip")]
     pub ip: String,
+    /// IPv6 address.
     #[serde(rename = "ipv6Address")]
     pub ipv6_address: String,
+    /// Bytes transmitted and received in the last sampling interval.
     #[serde(rename = "txRx")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_rx: u64,
+    /// Noise floor of the 2.4 GHz radio in dBm.
     #[serde(rename = "noise24G")]
     pub noise_24g: i64,
+    /// Noise floor of the 5 GHz radio in dBm.
     #[serde(rename = "noise5G")]
     pub noise_5g: i64,
+    /// Noise floor of the 6 GHz radio in dBm.
     #[serde(rename = "noise6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub noise_6g: i64,
+    /// Airtime utilization of the 2.4 GHz radio in percent.
     #[serde(rename = "airtime24G")]
     pub airtime_24g: u64,
+    /// Airtime utilization of the 5 GHz radio in percent.
     #[serde(rename = "airtime5G")]
     pub airtime_5g: u64,
+    /// Airtime utilization of the 6 GHz radio in percent.
     #[serde(rename = "airtime6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub airtime_6g: u64,
+    /// Latency reported by the 2.4 GHz radio.
     #[serde(rename = "latency24G")]
     pub latency24g: u64,
+    /// Latency reported by the 5 GHz radio.
     #[serde(rename = "latency50G")]
     pub latency50_g: u64,
+    /// Latency reported by the 6 GHz radio.
     #[serde(rename = "latency6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub latency6_g: u64,
+    /// Estimated capacity of all radios.
     #[serde(rename = "capacity")]
     pub capacity: u64,
+    /// Estimated capacity of the 2.4 GHz radio.
     #[serde(rename = "capacity24G")]
     pub capacity24_g: u64,
+    /// Estimated capacity of the 5 GHz radio.
     #[serde(rename = "capacity50G")]
     pub capacity50_g: u64,
+    /// Estimated capacity of the 6 GHz radio.
     #[serde(rename = "capacity6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub capacity6_g: u64,
+    /// Rate of failed client connections.
     #[serde(rename = "connectionFailure")]
     pub connection_failures: f64,
+    /// Hardware model.
     #[serde(rename = "model")]
     pub model: String,
+    /// The AP's MAC, what it is keyed by.
     #[serde(rename = "apMac")]
     pub ap_mac: String,
+    /// Channel of the 2.4 GHz radio as shown in the ui, with its width.
     #[serde(rename = "channel24G")]
     pub channel_24g: String,
+    /// Channel of the 5 GHz radio as shown in the ui, with its width.
     #[serde(rename = "channel5G")]
     pub channel_5g: String,
+    /// Channel of the 6 GHz radio as shown in the ui, with its width.
     #[serde(rename = "channel6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub channel_6g: String,
+    /// Channel number of the 2.4 GHz radio, 0 if it is off.
     #[serde(rename = "channel24gValue")]
     pub channel_24g_value: i64,
+    /// Channel number of the 5 GHz radio, 0 if it is off.
     #[serde(rename = "channel50gValue")]
     pub channel50g_value: u64,
+    /// Channel number of the 6 GHz radio, 0 if it is off.
     #[serde(rename = "channel6gValue")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub channel6g_value: u64,
+    /// Role in a mesh network.
     #[serde(rename = "meshRole")]
     pub mesh_role: String,
+    /// Mesh setting of the AP.
     #[serde(rename = "meshMode")]
     pub mesh_mode: String,
+    /// Name of the AP's zone.
     #[serde(rename = "zoneName")]
     pub zone_name: String,
+    /// Zone affinity profile the AP picks its control plane by.
     #[serde(rename = "zoneAffinityProfileName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub zone_affinity_profile_name: String,
+    /// Name of the AP's group.
     #[serde(rename = "apGroupName")]
     pub ap_group_name: String,
+    /// Address the controller sees the AP connect from, differs from `ip` behind NAT.
     #[serde(rename = "extIp")]
     pub ext_ip: String,
+    /// Port the controller sees the AP connect from.
     #[serde(rename = "extPort")]
     pub ext_port: String,
+    /// Firmware the AP runs.
     #[serde(rename = "firmwareVersion")]
     pub firmware_version: String,
+    /// Serial number.
     #[serde(rename = "serial")]
     pub serial: String,
+    /// Retry rate of the 2.4 GHz radio.
     #[serde(rename = "retry24G")]
     pub retry24_g: u64,
+    /// Retry rate of the 5 GHz radio.
     #[serde(rename = "retry5G")]
     pub retry5_g: u64,
+    /// Retry rate of the 6 GHz radio.
     #[serde(rename = "retry6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub retry6_g: u64,
+    /// Whether the AP runs the configuration the controller has for it.
     #[serde(rename = "configurationStatus")]
     pub configuration_status: String,
+    /// When the controller last heard from the AP, in milliseconds since the epoch.
     #[serde(rename = "lastSeen")]
     pub last_seen: u64,
+    /// Clients connected to all radios.
     #[serde(rename = "numClients")]
     pub num_clients: u64,
+    /// Clients connected to the 2.4 GHz radio.
     #[serde(rename = "numClients24G")]
     pub num_clients_24g: u64,
+    /// Clients connected to the 5 GHz radio.
     #[serde(rename = "numClients5G")]
     pub num_clients_5g: u64,
+    /// Clients connected to the 6 GHz radio.
     #[serde(rename = "numClients6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub num_clients_6g: u64,
+    /// Bytes transmitted in the last sampling interval.
     #[serde(rename = "tx")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx: u64,
+    /// Bytes transmitted by the 2.4 GHz radio in the last sampling interval.
     #[serde(rename = "tx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_24g: u64,
+    /// Bytes transmitted by the 5 GHz radio in the last sampling interval.
     #[serde(rename = "tx50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_50g: u64,
+    /// Bytes transmitted by the 6 GHz radio in the last sampling interval.
     #[serde(rename = "tx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_6g: u64,
+    /// Bytes received in the last sampling interval.
     #[serde(rename = "rx")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx: u64,
+    /// Bytes received by the 2.4 GHz radio in the last sampling interval.
     #[serde(rename = "rx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_24g: u64,
+    /// Bytes received by the 5 GHz radio in the last sampling interval.
     #[serde(rename = "rx50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_50g: u64,
+    /// Bytes received by the 6 GHz radio in the last sampling interval.
     #[serde(rename = "rx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_6g: u64,
+    /// Bytes transmitted and received by the 2.4 GHz radio in the last sampling interval.
     #[serde(rename = "txRx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_rx_24g: u64,
+    /// Bytes transmitted and received by the 5 GHz radio in the last sampling interval.
     #[serde(rename = "txRx50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_rx_50g: u64,
+    /// Bytes transmitted and received by the 6 GHz radio in the last sampling interval.
     #[serde(rename = "txRx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_rx_6g: u64,
+    /// Location text set in the controller.
     #[serde(rename = "location")]
     pub location: String,
+    /// Id of the WLAN group on the 2.4 GHz radio.
     #[serde(rename = "wlanGroup24Id")]
     pub wlan_group24_id: String,
+    /// Id of the WLAN group on the 5 GHz radio.
     #[serde(rename = "wlanGroup50Id")]
     pub wlan_group50_id: String,
+    /// Id of the WLAN group on the 6 GHz radio.
     #[serde(rename = "wlanGroup6gId")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub wlan_group6g_id: String,
+    /// Name of the WLAN group on the 2.4 GHz radio.
     #[serde(rename = "wlanGroup24Name")]
     pub wlan_group24_name: String,
+    /// Name of the WLAN group on the 5 GHz radio.
     #[serde(rename = "wlanGroup50Name")]
     pub wlan_group50_name: String,
+    /// Name of the WLAN group on the 6 GHz radio.
     #[serde(rename = "wlanGroup6gName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub wlan_group6g_name: String,
+    /// Whether the AP acts as a Bonjour gateway.
     #[serde(rename = "enabledBonjourGateway")]
     pub enabled_bonjour_gateway: bool,
+    /// Control plane the AP is managed by.
     #[serde(rename = "controlBladeName")]
     pub control_blade_name: String,
+    /// Status of the location based service.
     #[serde(rename = "lbsStatus")]
     pub lbs_status: String,
+    /// Whether the AP is locked or unlocked by an administrator.
     #[serde(rename = "administrativeState")]
     pub administrative_state: String,
+    /// Whether the AP was approved by the controller.
     #[serde(rename = "registrationState")]
     pub registration_state: String,
+    /// How the AP was added to the controller.
     #[serde(rename = "provisionMethod")]
     pub provision_method: String,
+    /// How far provisioning got.
     #[serde(rename = "provisionStage")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub provision_stage: String,
+    /// When the AP registered, in milliseconds since the epoch.
     #[serde(rename = "registrationTime")]
     pub registration_time: u64,
+    /// VLAN of the AP's management traffic.
     #[serde(rename = "managementVlan")]
     pub management_vlan: u64,
+    /// Whether settings of the AP override those of its group.
     #[serde(rename = "configOverride")]
     pub config_override: bool,
+    /// Id of the floor plan the AP is placed on.
     #[serde(rename = "indoorMapId")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub indoor_map_id: String,
+    /// Id of the AP's group.
     #[serde(rename = "apGroupId")]
     pub ap_group_id: String,
+    /// Name of the floor plan the AP is placed on.
     #[serde(rename = "indoorMapName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub indoor_map_name: String,
+    /// Location of the floor plan the AP is placed on.
     #[serde(rename = "indoorMapLocation")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub indoor_map_location: String,
+    /// GPS coordinates of the AP, empty if not set.
     #[serde(rename = "deviceGps")]
     pub device_gps: String,
+    /// Whether the AP is connected to the controller.
     #[serde(rename = "connectionStatus")]
     pub connection_status: String,
+    /// Id of the AP's zone.
     #[serde(rename = "zoneId")]
     pub zone_id: String,
+    /// Firmware version of the AP's zone.
     #[serde(rename = "zoneFirmwareVersion")]
     pub zone_firmware_version: String,
+    /// Id of the AP's domain.
     #[serde(rename = "domainId")]
     pub domain_id: String,
+    /// Name of the AP's domain.
     #[serde(rename = "domainName")]
     pub domain_name: String,
+    /// Id of the partner domain, empty outside of managed service setups.
     #[serde(rename = "partnerDomainId")]
     pub partner_domain_id: String,
+    /// Address of the data plane the AP tunnels to.
     #[serde(rename = "dpIp")]
     pub dp_ip: String,
+    /// Id of the control plane the AP is managed by.
     #[serde(rename = "controlBladeId")]
     pub control_blade_id: String,
+    /// Whether the AP is marked as critical.
     #[serde(rename = "isCriticalAp")]
     pub is_critical_ap: bool,
+    /// Number of crash dumps the AP uploaded.
     #[serde(rename = "crashDump")]
     pub crash_dump: u64,
+    /// Whether the AP has a cable modem.
     #[serde(rename = "cableModemSupported")]
     pub cable_modem_supported: bool,
+    /// Whether the AP's cable modem can be reset from the controller.
     #[serde(rename = "cableModemResetSupported")]
     pub cable_modem_reset_supported: bool,
     // "swapInMac": null,
+    /// MAC of the AP this one is replaced by in a swap.
     #[serde(rename = "swapInMac")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub swap_in_mac: String,
     // "swapOutMac": null,
+    /// MAC of the AP this one replaces in a swap.
     #[serde(rename = "swapOutMac")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub swap_out_mac: String,
+    /// Whether any health threshold of the AP is crossed.
     #[serde(rename = "isOverallHealthStatusFlagged")]
     pub is_overall_health_status_flagged: bool,
+    /// Whether the 2.4 GHz latency is over the health threshold.
     #[serde(rename = "isLatency24GFlagged")]
     pub is_latency_24g_flagged: bool,
+    /// Whether the 5 GHz latency is over the health threshold.
     #[serde(rename = "isLatency50GFlagged")]
     pub is_latency_50g_flagged: bool,
+    /// Whether the 6 GHz latency is over the health threshold.
     #[serde(rename = "isLatency6GFlagged")]
+    #[serde(deserialize_with = "deserialize_null_default")]
     pub is_latency6_gflagged: bool,
+    /// Whether the 2.4 GHz capacity is under the health threshold.
     #[serde(rename = "isCapacity24GFlagged")]
     pub is_capacity24_gflagged: bool,
+    /// Whether the 5 GHz capacity is under the health threshold.
     #[serde(rename = "isCapacity50GFlagged")]
     pub is_capacity50_gflagged: bool,
+    /// Whether the 6 GHz capacity is under the health threshold.
     #[serde(rename = "isCapacity6GFlagged")]
+    #[serde(deserialize_with = "deserialize_null_default")]
     pub is_capacity6_gflagged: bool,
+    /// Whether 2.4 GHz connection failures are over the health threshold.
     #[serde(rename = "isConnectionFailure24GFlagged")]
     pub is_connection_failure24_gflagged: bool,
+    /// Whether 5 GHz connection failures are over the health threshold.
     #[serde(rename = "isConnectionFailure50GFlagged")]
     pub is_connection_failure50_gflagged: bool,
+    /// Whether 6 GHz connection failures are over the health threshold.
     #[serde(rename = "isConnectionFailure6GFlagged")]
+    #[serde(deserialize_with = "deserialize_null_default")]
     pub is_connection_failure6_gflagged: bool,
+    /// Whether the number of connections is over the health threshold.
     #[serde(rename = "isConnectionTotalCountFlagged")]
     pub is_connection_total_count_flagged: bool,
+    /// Whether connection failures of all radios are over the health threshold.
     #[serde(rename = "isConnectionFailureFlagged")]
     pub is_connection_failure_flagged: bool,
+    /// Whether 2.4 GHz airtime utilization is over the health threshold.
     #[serde(rename = "isAirtimeUtilization24GFlagged")]
     pub is_airtime_utilization24_gflagged: bool,
+    /// Whether 5 GHz airtime utilization is over the health threshold.
     #[serde(rename = "isAirtimeUtilization50GFlagged")]
     pub is_airtime_utilization50_gflagged: bool,
+    /// Whether 6 GHz airtime utilization is over the health threshold.
     #[serde(rename = "isAirtimeUtilization6GFlagged")]
+    #[serde(deserialize_with = "deserialize_null_default")]
     pub is_airtime_utilization6_gflagged: bool,
+    /// Seconds since the AP came up.
     #[serde(rename = "uptime")]
     pub uptime: u64,
+    /// Effective isotropic radiated power of the 2.4 GHz radio in dBm.
     #[serde(rename = "eirp24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub eirp24_g: u64,
+    /// Effective isotropic radiated power of the 5 GHz radio in dBm.
     #[serde(rename = "eirp50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub eirp50_g: u64,
+    /// Effective isotropic radiated power of the 6 GHz radio in dBm.
     #[serde(rename = "eirp6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub eirp6_g: u64,
+    /// Whether the AP supports FIPS mode.
     #[serde(rename = "supportFips")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub support_fips: String,
+    /// Seconds the IPsec tunnel has been up.
     #[serde(rename = "ipsecSessionTime")]
     pub ipsec_session_time: u64,
+    /// Packets sent through the IPsec tunnel.
     #[serde(rename = "ipsecTxPkts")]
     pub ipsec_tx_pkts: u64,
+    /// Packets received through the IPsec tunnel.
     #[serde(rename = "ipsecRxPkts")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_rx_pkts: u64,
+    /// Bytes sent through the IPsec tunnel.
     #[serde(rename = "ipsecTxBytes")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_tx_bytes: u64,
+    /// Bytes received through the IPsec tunnel.
     #[serde(rename = "ipsecRxBytes")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_rx_bytes: u64,
+    /// Packets dropped before going through the IPsec tunnel.
     #[serde(rename = "ipsecTxDropPkts")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_tx_drop_pkts: u64,
+    /// Packets dropped after coming through the IPsec tunnel.
     #[serde(rename = "ipsecRxDropPkts")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_rx_drop_pkts: u64,
+    /// Seconds since anything was sent through the IPsec tunnel.
     #[serde(rename = "ipsecTxIdleTime")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_tx_idle_time: u64,
+    /// Seconds since anything came through the IPsec tunnel.
     #[serde(rename = "ipsecRxIdleTime")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub ipsec_rx_idle_time: u64,
+    /// How the IPv4 address is assigned, static or DHCP.
     #[serde(rename = "ipType")]
     pub ip_type: String,
+    /// How the IPv6 address is assigned.
     #[serde(rename = "ipv6Type")]
     pub ipv6_type: String,
+    /// Whether a packet capture is running on the AP.
     #[serde(rename = "packetCaptureState")]
     pub packet_capture_state: String,
+    /// Cellular interface used as WAN uplink, empty without a modem.
     #[serde(rename = "cellularWanInterface")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_wan_interface: String,
+    /// Whether the cellular uplink is connected.
     #[serde(rename = "cellularConnectionStatus")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_connection_status: String,
+    /// Signal strength of the cellular uplink.
     #[serde(rename = "cellularSignalStrength")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_signal_strength: String,
+    /// IMSI of the first SIM.
     #[serde(rename = "cellularIMSISIM0")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_imsisim0: String,
+    /// IMSI of the second SIM.
     #[serde(rename = "cellularIMSISIM1")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_imsisim1: String,
+    /// ICCID of the first SIM.
     #[serde(rename = "cellularICCIDSIM0")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_iccidsim0: String,
+    /// ICCID of the second SIM.
     #[serde(rename = "cellularICCIDSIM1")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_iccidsim1: String,
+    /// Whether the first SIM slot is in use.
     #[serde(rename = "cellularIsSIM0Present")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_is_sim0_present: String,
+    /// Whether the second SIM slot is in use.
     #[serde(rename = "cellularIsSIM1Present")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_is_sim1_present: String,
+    /// Bytes sent over the first SIM.
     #[serde(rename = "cellularTxBytesSIM0")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_tx_bytes_sim0: String,
+    /// Bytes sent over the second SIM.
     #[serde(rename = "cellularTxBytesSIM1")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_tx_bytes_sim1: String,
+    /// Bytes received over the first SIM.
     #[serde(rename = "cellularRxBytesSIM0")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_rx_bytes_sim0: String,
+    /// Bytes received over the second SIM.
     #[serde(rename = "cellularRxBytesSIM1")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_rx_bytes_sim1: String,
+    /// The SIM in use.
     #[serde(rename = "cellularActiveSim")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_active_sim: String,
+    /// Address of the cellular uplink.
     #[serde(rename = "cellularIPaddress")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_ipaddress: String,
+    /// Subnet mask of the cellular uplink.
     #[serde(rename = "cellularSubnetMask")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_subnet_mask: String,
+    /// Default gateway of the cellular uplink.
     #[serde(rename = "cellularDefaultGateway")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_default_gateway: String,
+    /// Mobile network operator.
     #[serde(rename = "cellularOperator")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_operator: String,
+    /// Channel of the 3G or 4G connection.
     #[serde(rename = "cellular3G4GChannel")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular3_g4_gchannel: String,
+    /// Country of the mobile network.
     #[serde(rename = "cellularCountry")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_country: String,
+    /// How long the cellular radio has been up.
     #[serde(rename = "cellularRadioUptime")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_radio_uptime: String,
+    /// GPS positions reported by the cellular modem.
     #[serde(rename = "cellularGpsHistory")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cellular_gps_history: String,
+    /// Whether FIPS mode is on.
     #[serde(rename = "fipsEnabled")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub fips_enabled: String,
+    /// Median transmit MCS rate of the 2.4 GHz radio.
     #[serde(rename = "medianTxRadioMCSRate24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_tx_radio_mcsrate24_g: u64,
+    /// Median transmit MCS rate of the 5 GHz radio.
     #[serde(rename = "medianTxRadioMCSRate50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_tx_radio_mcsrate50_g: u64,
+    /// Median transmit MCS rate of the 6 GHz radio.
     #[serde(rename = "medianTxRadioMCSRate6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_tx_radio_mcsrate6_g: u64,
+    /// Median receive MCS rate of the 2.4 GHz radio.
     #[serde(rename = "medianRxRadioMCSRate24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_rx_radio_mcsrate24_g: u64,
+    /// Median receive MCS rate of the 5 GHz radio.
     #[serde(rename = "medianRxRadioMCSRate50G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_rx_radio_mcsrate50_g: u64,
+    /// Median receive MCS rate of the 6 GHz radio.
     #[serde(rename = "medianRxRadioMCSRate6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub median_rx_radio_mcsrate6_g: u64,
+    /// Whether the AP is monitored by the controller.
     #[serde(rename = "monitoringEnabled")]
     pub monitoring_enabled: bool,
+    /// Transmit power offset of the 2.4 GHz radio in dB.
     #[serde(rename = "txPowerOffset24G")]
     pub tx_power_offset24_g: i64,
+    /// Transmit power offset of the 5 GHz radio in dB.
     #[serde(rename = "txPowerOffset5G")]
     pub tx_power_offset5_g: i64,
+    /// Transmit power offset of the 6 GHz radio in dB.
     #[serde(rename = "txPowerOffset6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_power_offset6_g: i64,
+    /// Receive desensitization of the 2.4 GHz radio in dB.
     #[serde(rename = "rxDesense24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_desense24_g: i64,
+    /// Receive desensitization of the 5 GHz radio in dB.
     #[serde(rename = "rxDesense5G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_desense5_g: i64,
+    /// Receive desensitization of the 6 GHz radio in dB.
     #[serde(rename = "rxDesense6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rx_desense6_g: i64,
+    /// PoE status of the AP's switch port.
     #[serde(rename = "poePortStatus")]
     pub poe_port_status: String,
+    /// Bytes transmitted by the 2.4 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx24_g: u64,
+    /// Bytes transmitted by the 5 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTx5G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx5_g: u64,
+    /// Bytes transmitted by the 6 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx6_g: u64,
+    /// Bytes received by the 2.4 GHz radio since the AP came up.
     #[serde(rename = "cumulativeRx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_rx24_g: u64,
+    /// Bytes received by the 5 GHz radio since the AP came up.
     #[serde(rename = "cumulativeRx5G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_rx5_g: u64,
+    /// Bytes received by the 6 GHz radio since the AP came up.
     #[serde(rename = "cumulativeRx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_rx6_g: u64,
+    /// Bytes transmitted and received by the 2.4 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTxRx24G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx_rx24_g: u64,
+    /// Bytes transmitted and received by the 5 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTxRx5G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx_rx5_g: u64,
+    /// Bytes transmitted and received by the 6 GHz radio since the AP came up.
     #[serde(rename = "cumulativeTxRx6G")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub cumulative_tx_rx6_g: u64,
+    /// Whether the AP runs two 5 GHz radios instead of a 5 and a 6 GHz one.
     #[serde(rename = "isDual5gMode")]
     pub is_dual5g_mode: bool,
+    /// Position of the AP on its floor plan.
     #[serde(rename = "indoorMapXy")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub indoor_map_xy: MapXY,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Default)]
+/// The radio a per band value belongs to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Band {
+    /// 2.4 GHz.
+    G24,
+    /// 5 GHz.
+    G5,
+    /// 6 GHz.
+    G6,
+}
+
+impl Band {
+    /// Value of the `Band` label.
+    pub fn label(self) -> &'static str {
+        match self {
+            Band::G24 => "2.4",
+            Band::G5 => "5",
+            Band::G6 => "6",
+        }
+    }
+}
+
+/// The per band fields of an [`AP`] gathered up, so every band can be
+/// exported under the same metric name.
+pub struct Radio {
+    /// Which of the AP's radios this is.
+    pub band: Band,
+    /// Channel number, see [`AP::channel_24g_value`].
+    pub channel: i64,
+    /// Noise floor in dBm.
+    pub noise: i64,
+    /// Airtime utilization in percent.
+    pub airtime: u64,
+    /// Latency reported by the radio.
+    pub latency: u64,
+    /// Estimated capacity.
+    pub capacity: u64,
+    /// Retry rate.
+    pub retries: u64,
+    /// Bytes transmitted in the last sampling interval.
+    pub tx: u64,
+    /// Bytes received in the last sampling interval.
+    pub rx: u64,
+    /// Clients connected to the radio.
+    pub clients: u64,
+    /// Effective isotropic radiated power in dBm.
+    pub eirp: u64,
+    /// Median transmit MCS rate.
+    pub median_tx_mcs_rate: u64,
+    /// Median receive MCS rate.
+    pub median_rx_mcs_rate: u64,
+    /// Transmit power offset in dB.
+    pub tx_power_offset: i64,
+    /// Receive desensitization in dB.
+    pub rx_desense: i64,
+}
+
+impl AP {
+    /// The 2.4 and 5 GHz radios, plus the 6 GHz one if the AP has it on a channel.
+    pub fn radios(&self) -> Vec<Radio> {
+        let mut radios = vec![
+            Radio {
+                band: Band::G24,
+                channel: self.channel_24g_value,
+                noise: self.noise_24g,
+                airtime: self.airtime_24g,
+                latency: self.latency24g,
+                capacity: self.capacity24_g,
+                retries: self.retry24_g,
+                tx: self.tx_24g,
+                rx: self.rx_24g,
+                clients: self.num_clients_24g,
+                eirp: self.eirp24_g,
+                median_tx_mcs_rate: self.median_tx_radio_mcsrate24_g,
+                median_rx_mcs_rate: self.median_rx_radio_mcsrate24_g,
+                tx_power_offset: self.tx_power_offset24_g,
+                rx_desense: self.rx_desense24_g,
+            },
+            Radio {
+                band: Band::G5,
+                channel: self.channel50g_value as i64,
+                noise: self.noise_5g,
+                airtime: self.airtime_5g,
+                latency: self.latency50_g,
+                capacity: self.capacity50_g,
+                retries: self.retry5_g,
+                tx: self.tx_50g,
+                rx: self.rx_50g,
+                clients: self.num_clients_5g,
+                eirp: self.eirp50_g,
+                median_tx_mcs_rate: self.median_tx_radio_mcsrate50_g,
+                median_rx_mcs_rate: self.median_rx_radio_mcsrate50_g,
+                tx_power_offset: self.tx_power_offset5_g,
+                rx_desense: self.rx_desense5_g,
+            },
+        ];
+        if self.channel6g_value > 0 {
+            radios.push(Radio {
+                band: Band::G6,
+                channel: self.channel6g_value as i64,
+                noise: self.noise_6g,
+                airtime: self.airtime_6g,
+                latency: self.latency6_g,
+                capacity: self.capacity6_g,
+                retries: self.retry6_g,
+                tx: self.tx_6g,
+                rx: self.rx_6g,
+                clients: self.num_clients_6g,
+                eirp: self.eirp6_g,
+                median_tx_mcs_rate: self.median_tx_radio_mcsrate6_g,
+                median_rx_mcs_rate: self.median_rx_radio_mcsrate6_g,
+                tx_power_offset: self.tx_power_offset6_g,
+                rx_desense: self.rx_desense6_g,
+            });
+        }
+        radios
+    }
+}
+
+impl Record for AP {
+    fn adapt(version: ApiVersion, record: &mut Map<String, Value>) {
+        if version < ApiVersion::new(11, 0) {
+            fill_missing(record, SIX_GHZ_FIELDS);
+        }
+    }
+}
+
+/// Where an AP is placed on its floor plan.
+#[derive(Deserialize, Serialize, Default)]
 pub struct MapXY {
+    /// Horizontal position on the floor plan.
     pub x: f32,
+    /// Vertical position on the floor plan.
     pub y: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An AP as v10_0 sends it, without the 6 GHz fields.
+    fn without_six_ghz() -> Map<String, Value> {
+        let Value::Object(mut record) = serde_json::to_value(AP::default()).unwrap() else {
+            unreachable!()
+        };
+        for field in SIX_GHZ_FIELDS {
+            record.remove(*field);
+        }
+        record.insert("channel50gValue".to_string(), Value::from(36));
+        record
+    }
+
+    #[test]
+    fn fills_six_ghz_fields_before_v11_0() {
+        let mut record = without_six_ghz();
+        AP::adapt(ApiVersion::V10_0, &mut record);
+        let ap: AP = serde_json::from_value(Value::Object(record)).unwrap();
+        assert_eq!(ap.channel6g_value, 0);
+        assert_eq!(ap.radios().len(), 2);
+    }
+
+    #[test]
+    fn expects_six_ghz_fields_from_v11_0() {
+        let mut record = without_six_ghz();
+        AP::adapt(ApiVersion::new(11, 0), &mut record);
+        assert!(serde_json::from_value::<AP>(Value::Object(record)).is_err());
+    }
+}
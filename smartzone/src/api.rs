@@ -0,0 +1,1069 @@
+use futures::{stream, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{HeaderValue, SET_COOKIE},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::{fmt, future::Future, sync::Arc, time::Instant};
+use tokio::sync::RwLock;
+
+use crate::{
+    cassette::{Cassette, CassetteMode},
+    error::SmartZoneError,
+    filter::FilterContainer,
+    tls::TlsConfig,
+    version::{self, ApiVersion},
+};
+
+/// A session with one controller. Built with [`Auth::builder`], logs in on
+/// the first request and renews the session when the controller expires it.
+#[derive(Serialize)]
+pub struct Auth {
+    #[serde(skip_serializing)]
+    url: String,
+    username: String,
+    password: String,
+    #[serde(rename = "timeZoneUtcOffset")]
+    time_zone_utc_offset: String,
+    #[serde(skip_serializing)]
+    client: reqwest::Client,
+    /// Forces an api version instead of negotiating the newest one.
+    #[serde(skip_serializing)]
+    api_version: Option<ApiVersion>,
+    /// Swapped out under the write lock when smartzone expires the cookie.
+    #[serde(skip_serializing)]
+    session: RwLock<Option<Session>>,
+    #[serde(skip_serializing)]
+    observer: Arc<dyn ApiObserver>,
+    /// Lets missing and `null` fields fall back to defaults, see [`Record`].
+    #[serde(skip_serializing)]
+    lenient: bool,
+    /// Records per page and how many pages are fetched at once in [`Auth::query_all`].
+    #[serde(skip_serializing)]
+    page_size: usize,
+    #[serde(skip_serializing)]
+    page_concurrency: usize,
+    /// Records the traffic, or replays it instead of using the network.
+    #[serde(skip_serializing)]
+    cassette: Option<Cassette>,
+}
+
+// Neither the password nor the session cookie may end up in a log line.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("time_zone_utc_offset", &self.time_zone_utc_offset)
+            .field("api_version", &self.api_version)
+            .field("observer", &self.observer)
+            .field("lenient", &self.lenient)
+            .field("page_size", &self.page_size)
+            .field("page_concurrency", &self.page_concurrency)
+            .field("cassette", &self.cassette)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Records per query page unless [`AuthBuilder::paging`] says otherwise.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Query pages fetched at the same time unless [`AuthBuilder::paging`] says
+/// otherwise.
+pub const DEFAULT_PAGE_CONCURRENCY: usize = 4;
+/// UTC offset sent with the login unless [`AuthBuilder::time_zone`] says
+/// otherwise.
+pub const DEFAULT_TIME_ZONE: &str = "-07:00";
+/// Upper bound on pages for one query, in case a controller reports a
+/// nonsensical totalCount.
+const MAX_PAGES: usize = 10_000;
+
+/// Told about every request an [`Auth`] makes and anything worth reporting
+/// along the way, e.g. to turn them into metrics or log lines. The library
+/// prints nothing itself. Every method does nothing by default.
+pub trait ApiObserver: fmt::Debug + Send + Sync {
+    /// `status` is `None` when the request failed without a response.
+    fn request(&self, _endpoint: &str, _seconds: f64, _status: Option<u16>) {}
+    /// A query page with `records` decoded and `skipped` left out.
+    fn page(&self, _query: &str, _records: usize, _skipped: usize) {}
+    /// A response or record that could not be decoded, with the json it was in.
+    fn decode_failure(&self, _endpoint: &str, _err: &SmartZoneError, _payload: &str) {}
+    /// A login attempt, whether or not the controller accepted it.
+    fn login(&self, _success: bool) {}
+    /// The session expired and was renewed.
+    fn relogin(&self) {}
+    /// The last page of a query still said `hasMore` after `total_count`
+    /// records, the rest were left out.
+    fn truncated(&self, _query: &str, _total_count: usize) {}
+    /// A system CA certificate could not be loaded, the others are still trusted.
+    fn certificate_skipped(&self, _err: &str) {}
+}
+
+/// Observes nothing.
+#[derive(Debug)]
+pub(crate) struct NoObserver;
+
+impl ApiObserver for NoObserver {}
+
+/// Sets up an [`Auth`], see [`Auth::builder`].
+pub struct AuthBuilder {
+    url: String,
+    username: String,
+    password: String,
+    time_zone: String,
+    tls: TlsConfig,
+    api_version: Option<ApiVersion>,
+    page_size: usize,
+    page_concurrency: usize,
+    lenient: bool,
+    cassette: Option<CassetteMode>,
+    observer: Arc<dyn ApiObserver>,
+}
+
+impl AuthBuilder {
+    /// How the controller's certificate is checked, the system trust store by default.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Uses this api version rather than the newest one both sides support.
+    pub fn api_version(mut self, version: Option<ApiVersion>) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Sets the page size and how many pages [`Auth::query_all`] requests at once.
+    pub fn paging(mut self, page_size: usize, page_concurrency: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self.page_concurrency = page_concurrency.max(1);
+        self
+    }
+
+    /// UTC offset of the session, [`DEFAULT_TIME_ZONE`] if not set.
+    pub fn time_zone(mut self, utc_offset: impl Into<String>) -> Self {
+        self.time_zone = utc_offset.into();
+        self
+    }
+
+    /// Decodes records even if some fields are missing or `null`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Writes every request and response to a cassette directory, or answers
+    /// from one without touching the network.
+    pub fn cassette(mut self, mode: Option<CassetteMode>) -> Self {
+        self.cassette = mode;
+        self
+    }
+
+    /// Reports requests, pages and failures, nothing is reported by default.
+    pub fn observer(mut self, observer: Arc<dyn ApiObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Builds the client without touching the network, the controller is
+    /// first contacted on [`Auth::login`] or the first request.
+    pub fn build(self) -> Result<Auth, SmartZoneError> {
+        let url = self.url.trim_end_matches('/').to_string();
+        let cassette = self.cassette.map(|mode| Cassette::open(&url, mode)).transpose()?;
+        Ok(Auth {
+            client: Client::builder()
+                .cookie_store(true)
+                .use_preconfigured_tls(self.tls.client_config(self.observer.as_ref())?)
+                .build()?,
+            url,
+            username: self.username,
+            password: self.password,
+            time_zone_utc_offset: self.time_zone,
+            api_version: self.api_version,
+            session: RwLock::new(None),
+            observer: self.observer,
+            lenient: self.lenient,
+            page_size: self.page_size,
+            page_concurrency: self.page_concurrency,
+            cassette,
+        })
+    }
+}
+
+// The password must never end up in a log line.
+impl fmt::Debug for AuthBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthBuilder")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("time_zone", &self.time_zone)
+            .field("tls", &self.tls)
+            .field("api_version", &self.api_version)
+            .field("page_size", &self.page_size)
+            .field("page_concurrency", &self.page_concurrency)
+            .field("lenient", &self.lenient)
+            .field("cassette", &self.cassette)
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+/// A logged in session and the api version negotiated for it.
+#[derive(Debug, Clone, PartialEq)]
+struct Session {
+    cookie: HeaderValue,
+    version: ApiVersion,
+}
+
+impl Auth {
+    /// Starts setting up a session with the controller at `url`, e.g.
+    /// `https://10.0.0.1:8443`.
+    pub fn builder(url: impl Into<String>, username: impl Into<String>, password: impl Into<String>) -> AuthBuilder {
+        AuthBuilder {
+            url: url.into(),
+            username: username.into(),
+            password: password.into(),
+            time_zone: DEFAULT_TIME_ZONE.to_string(),
+            tls: TlsConfig::default(),
+            api_version: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            page_concurrency: DEFAULT_PAGE_CONCURRENCY,
+            lenient: false,
+            cassette: None,
+            observer: Arc::new(NoObserver),
+        }
+    }
+
+    /// The controller's url without a trailing slash.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Asks the controller which api versions it offers. Done on every login
+    /// so a controller upgrade is picked up without a restart.
+    async fn negotiate_version(&self) -> Result<ApiVersion, SmartZoneError> {
+        #[derive(Deserialize)]
+        struct ApiInfo {
+            #[serde(rename = "apiSupportVersions")]
+            api_support_versions: Vec<String>
+        }
+
+        let url = format!("{}/wsg/api/public/apiInfo", self.url);
+        let res = check_status(self.execute("apiInfo", self.client.get(&url)).await?)?;
+        let body = res.text().await?;
+        let info = decode::<ApiInfo>(&body, "apiInfo").inspect_err(|err| self.decode_failed("apiInfo", err, &body))?;
+
+        version::negotiate(&self.url, &info.api_support_versions, self.api_version)
+    }
+
+    /// Sends a request and records how long it took and what came back.
+    async fn execute(&self, endpoint: &str, request: RequestBuilder) -> Result<Response, SmartZoneError> {
+        let request = request.build()?;
+        let start = Instant::now();
+        let response = match &self.cassette {
+            None => self.client.execute(request).await.map_err(SmartZoneError::from),
+            Some(cassette) if cassette.is_replay() => cassette.replay(&request),
+            Some(cassette) => {
+                // Every body sent to smartzone is a string, so this always clones
+                let sent = request.try_clone().expect("request body can be cloned");
+                match self.client.execute(request).await {
+                    Ok(response) => cassette.record(&sent, response).await,
+                    Err(err) => Err(err.into()),
+                }
+            }
+        };
+        let status = response.as_ref().ok().map(|r| r.status().as_u16());
+        self.observer.request(endpoint, start.elapsed().as_secs_f64(), status);
+        response
+    }
+
+    fn endpoint(&self, version: ApiVersion, path: &str) -> String {
+        format!("{}/wsg/api/public/{version}/{path}", self.url)
+    }
+
+    /// Logs in now rather than on the first request, replacing any session.
+    pub async fn login(&self) -> Result<(), SmartZoneError> {
+        let mut session = self.session.write().await;
+        *session = Some(self.create_session().await?);
+        Ok(())
+    }
+
+    async fn create_session(&self) -> Result<Session, SmartZoneError> {
+        let session = self.try_create_session().await;
+        self.observer.login(session.is_ok());
+        session
+    }
+
+    async fn try_create_session(&self) -> Result<Session, SmartZoneError> {
+        let version = self.negotiate_version().await?;
+        let body = serde_json::to_string(&self).map_err(|source| SmartZoneError::Encode {
+            context: "login request".to_string(),
+            source,
+        })?;
+        let login = self
+            .execute("session", self.client.post(self.endpoint(version, "session")).body(body))
+            .await?;
+
+        if login.status() != StatusCode::OK {
+            return Err(SmartZoneError::Auth(format!(
+                "login returned {}",
+                login.status()
+            )));
+        };
+
+        let cookie = login
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .next()
+            .cloned()
+            .ok_or_else(|| SmartZoneError::Auth("login response had no session cookie".to_string()))?;
+        Ok(Session { cookie, version })
+    }
+
+    /// Replaces the session if it is still the one that was rejected.
+    /// Concurrent requests that fail with the same cookie wait on the lock and
+    /// then reuse the session created by whoever got there first.
+    async fn relogin(&self, rejected: &Session) -> Result<(), SmartZoneError> {
+        let mut session = self.session.write().await;
+        if session.as_ref() == Some(rejected) {
+            *session = None;
+            *session = Some(self.create_session().await?);
+            self.observer.relogin();
+        }
+        Ok(())
+    }
+
+    async fn current_session(&self) -> Result<Session, SmartZoneError> {
+        if let Some(s) = &*self.session.read().await {
+            return Ok(s.clone());
+        }
+        let mut session = self.session.write().await;
+        match &*session {
+            Some(s) => Ok(s.clone()),
+            None => {
+                let s = self.create_session().await?;
+                *session = Some(s.clone());
+                Ok(s)
+            }
+        }
+    }
+
+    /// Sends an authenticated request, renewing the session and retrying once
+    /// if smartzone rejects the cookie. The request is built for the api
+    /// version of the session it is sent with.
+    async fn send<F>(&self, endpoint: &str, request: F) -> Result<(Response, ApiVersion), SmartZoneError>
+    where
+        F: Fn(&Client, ApiVersion) -> RequestBuilder,
+    {
+        let session = self.current_session().await?;
+        let response = self
+            .execute(endpoint, request(&self.client, session.version).header("Cookie", &session.cookie))
+            .await?;
+        if !is_session_rejected(response.status()) {
+            return Ok((check_status(response)?, session.version));
+        }
+
+        self.relogin(&session).await?;
+        let session = self.current_session().await?;
+        let response = self
+            .execute(endpoint, request(&self.client, session.version).header("Cookie", &session.cookie))
+            .await?;
+        if is_session_rejected(response.status()) {
+            return Err(SmartZoneError::Auth(format!(
+                "{} rejected the renewed session with {}",
+                response.url(),
+                response.status()
+            )));
+        }
+        Ok((check_status(response)?, session.version))
+    }
+
+    /// Ends the session if there is one.
+    pub async fn logout(&self) -> Result<(), SmartZoneError> {
+        if let Some(s) = &*self.session.read().await {
+            let res = self
+                .execute(
+                    "session",
+                    self.client.delete(self.endpoint(s.version, "session")).header("Cookie", &s.cookie),
+                )
+                .await?;
+            check_status(res)?;
+        }
+        Ok(())
+    }
+
+    /// Every zone on the controller.
+    pub async fn get_zones(&self) -> Result<Vec<Zone>, SmartZoneError> {
+        #[derive(Deserialize)]
+        struct Zones {
+            list: Vec<Zone>,
+        }
+
+        let (response, _) = self
+            .send("rkszones", |client, version| client.get(self.endpoint(version, "rkszones")))
+            .await?;
+        let json = response.text().await?;
+        let zones = decode::<Zones>(&json, "rkszones").inspect_err(|err| self.decode_failed("rkszones", err, &json))?;
+        Ok(zones.list)
+    }
+
+    /// Fetches every page of a query. The first page tells how many records
+    /// there are, the remaining pages are then requested concurrently.
+    ///
+    /// Only the pages covered by `totalCount` are fetched, so a controller
+    /// that keeps answering `hasMore: true` can not keep this going forever.
+    /// The observer is told when that cuts records off.
+    ///
+    /// The `page` and `limit` of `filter` are ignored, every page is as large
+    /// as set with [`AuthBuilder::paging`]. Use [`Auth::query`] to fetch a
+    /// single page of a chosen size.
+    pub async fn query_all<T: Record>(&self, mut filter: FilterContainer, opt: Query) -> Result<Vec<T>, SmartZoneError> {
+        filter.page = 1;
+        filter.limit = self.page_size;
+        let first = self.query::<T>(filter.clone(), opt).await?;
+        let pages = first.total_count.div_ceil(self.page_size).clamp(1, MAX_PAGES);
+
+        let rest: Vec<QueryResults<T>> = stream::iter(2..=pages)
+            .map(|page| {
+                let filter = FilterContainer { page, ..filter.clone() };
+                self.query::<T>(filter, opt)
+            })
+            .buffered(self.page_concurrency)
+            .try_collect()
+            .await?;
+
+        if rest.last().unwrap_or(&first).has_more {
+            self.observer.truncated(opt.name(), first.total_count);
+        }
+
+        let mut all = first.list;
+        for mut page in rest {
+            all.append(&mut page.list);
+        }
+        Ok(all)
+    }
+
+    /// Fetches the single page of a query `filter` asks for. Records that do
+    /// not decode are skipped and reported to the observer.
+    pub async fn query<T: Record>(&self, filter: FilterContainer, opt: Query) -> Result<QueryResults<T>, SmartZoneError> {
+        let ttype = opt.name();
+
+        let body = serde_json::to_string(&filter).map_err(|source| SmartZoneError::Encode {
+            context: format!("query/{ttype} filter"),
+            source,
+        })?;
+        let endpoint = format!("query/{ttype}");
+        let (response, version) = self
+            .send(&endpoint, |client, version| {
+                client
+                    .post(self.endpoint(version, &format!("query/{ttype}")))
+                    .body(body.clone())
+            })
+            .await?;
+        let json = response.text().await?;
+        let context = format!("query/{ttype} page {}", filter.page);
+        let mut page = decode::<QueryResults<Value>>(&json, &context)
+            .inspect_err(|err| self.decode_failed(&endpoint, err, &json))?;
+        let (list, failed) = page.adapt::<T>(version, &context, self.lenient, &filter.attributes);
+        self.observer.page(ttype, list.len(), failed.len());
+        for (err, record) in failed {
+            let record = serde_json::to_string_pretty(&record).unwrap_or_default();
+            self.decode_failed(&endpoint, &err, &record);
+        }
+        Ok(page.with_list(list))
+    }
+
+    fn decode_failed(&self, endpoint: &str, err: &SmartZoneError, body: &str) {
+        self.observer.decode_failure(endpoint, err, body);
+    }
+
+}
+
+/// What the collectors need from a controller, so they can be driven by
+/// something other than a live [`Auth`] session.
+pub trait SmartZoneApi: Send + Sync {
+    /// Where the controller is, for telling controllers apart.
+    fn url(&self) -> &str;
+
+    /// Every zone on the controller.
+    fn zones(&self) -> impl Future<Output = Result<Vec<Zone>, SmartZoneError>> + Send;
+
+    /// Every record of one kind in `zone`, with only the fields in
+    /// `attributes` unless it is empty.
+    fn query_zone<T: Record + Send>(
+        &self,
+        zone: &Zone,
+        query: Query,
+        attributes: &[&str],
+    ) -> impl Future<Output = Result<Vec<T>, SmartZoneError>> + Send;
+}
+
+impl SmartZoneApi for Auth {
+    fn url(&self) -> &str {
+        Auth::url(self)
+    }
+
+    async fn zones(&self) -> Result<Vec<Zone>, SmartZoneError> {
+        self.get_zones().await
+    }
+
+    async fn query_zone<T: Record + Send>(
+        &self,
+        zone: &Zone,
+        query: Query,
+        attributes: &[&str],
+    ) -> Result<Vec<T>, SmartZoneError> {
+        self.query_all(FilterContainer::from(zone).attributes(attributes.iter().copied()), query).await
+    }
+}
+
+fn is_session_rejected(status: StatusCode) -> bool {
+    matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+}
+
+fn check_status(response: Response) -> Result<Response, SmartZoneError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(SmartZoneError::Status {
+            url: response.url().to_string(),
+            status: response.status(),
+        })
+    }
+}
+
+fn decode<T>(json: &str, context: &str) -> Result<T, SmartZoneError>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(json)).map_err(|err| SmartZoneError::Decode {
+        context: context.to_string(),
+        path: path_of(&err),
+        source: err.into_inner(),
+    })
+}
+
+/// A zone (domain of APs) as listed by `rkszones`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Zone {
+    /// The zone's uuid, what queries filter on.
+    pub id: String,
+    /// The name shown in the controller's ui.
+    pub name: String,
+}
+
+/// One page of a query.
+#[derive(Deserialize, Debug)]
+pub struct QueryResults<T> {
+    /// Records matching the query over all pages.
+    #[serde(rename = "totalCount")]
+    pub total_count: usize,
+    /// Whether there are pages after this one.
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    /// Index of the first record of this page among all of them.
+    #[serde(rename = "firstIndex")]
+    pub first_index: usize,
+    /// The records of this page.
+    pub list: Vec<T>
+}
+
+impl<T> QueryResults<T> {
+    /// Swaps the records for `list`, keeping the paging fields.
+    fn with_list<U>(self, list: Vec<U>) -> QueryResults<U> {
+        QueryResults {
+            total_count: self.total_count,
+            has_more: self.has_more,
+            first_index: self.first_index,
+            list,
+        }
+    }
+}
+
+impl QueryResults<Value> {
+    /// Takes the records out and decodes them one by one, rewriting each
+    /// into the shape `T` expects first. Records that fail are left out and
+    /// returned with the error so the rest of the page is still used.
+    ///
+    /// Fields left out of a non-empty `attributes` are missing on purpose and
+    /// take their defaults, the ones asked for are decoded strictly unless
+    /// `lenient`.
+    fn adapt<T: Record>(
+        &mut self,
+        version: ApiVersion,
+        context: &str,
+        lenient: bool,
+        attributes: &[String],
+    ) -> (Vec<T>, Vec<(SmartZoneError, Value)>) {
+        let strict: &[String] = if lenient { &[] } else { attributes };
+        let template = (lenient || !attributes.is_empty()).then(|| serde_json::to_value(T::default()).unwrap_or_default());
+        let mut decoded = Vec::with_capacity(self.list.len());
+        let mut failed = Vec::new();
+        for (i, mut record) in std::mem::take(&mut self.list).into_iter().enumerate() {
+            if let Value::Object(fields) = &mut record {
+                T::adapt(version, fields);
+                if let Some(Value::Object(template)) = &template {
+                    fill_defaults(fields, template, strict);
+                }
+            }
+            match serde_path_to_error::deserialize(&record) {
+                Ok(item) => decoded.push(item),
+                Err(err) => {
+                    let err = SmartZoneError::Decode {
+                        context: context.to_string(),
+                        path: format!("list[{i}]{}", path_of(&err)),
+                        source: err.into_inner(),
+                    };
+                    failed.push((err, record));
+                }
+            }
+        }
+        (decoded, failed)
+    }
+}
+
+/// `.rssi` style path of where decoding stopped, serde_path_to_error
+/// writes `.` for the root.
+fn path_of(err: &serde_path_to_error::Error<serde_json::Error>) -> String {
+    match err.path().to_string() {
+        root if root == "." => String::new(),
+        path if path.starts_with('[') => path,
+        path => format!(".{path}"),
+    }
+}
+
+/// A record returned by the query api.
+///
+/// The structs are written against v11_1. Differences in other api versions
+/// are handled in `adapt`, which gets the raw json object before it is decoded.
+/// `Default` and `Serialize` give the values lenient decoding falls back to.
+pub trait Record: for<'a> Deserialize<'a> + Serialize + Default {
+    /// Rewrites `record`, sent by a controller speaking `version`, into the
+    /// shape of v11_1.
+    fn adapt(_version: ApiVersion, _record: &mut Map<String, Value>) {}
+}
+
+/// Inserts `null` for fields the api version does not send yet, so they fall
+/// back to their defaults through [`deserialize_null_default`].
+pub(crate) fn fill_missing(record: &mut Map<String, Value>, fields: &[&str]) {
+    for field in fields {
+        record.entry(*field).or_insert(Value::Null);
+    }
+}
+
+/// Lenient decoding: fields that are missing or `null` take the value of
+/// the same field in `T::default()`, so new firmware that drops a field or
+/// starts sending `null` does not cost the whole record. Fields in `strict`
+/// are left as they are.
+fn fill_defaults(record: &mut Map<String, Value>, template: &Map<String, Value>, strict: &[String]) {
+    for (field, default) in template.iter().filter(|(field, _)| !strict.contains(field)) {
+        let value = record.entry(field.as_str()).or_insert(Value::Null);
+        if value.is_null() {
+            *value = default.clone();
+        }
+    }
+}
+
+/// What a query returns.
+#[derive(Clone, Copy, Debug)]
+pub enum Query {
+    /// [`crate::client::Client`] records from `query/client`.
+    Clients,
+    /// [`crate::ap::AP`] records from `query/ap`.
+    Aps,
+}
+
+impl Query {
+    /// As it appears in the endpoint, `query/<name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clients => "client",
+            Self::Aps => "ap",
+        }
+    }
+}
+
+/// Decodes `null` as the default value, for fields the controller leaves empty.
+pub(crate) fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Default + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let opt = Option::deserialize(deserializer)?;
+    Ok(opt.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// What the test controller sends back for one request.
+    struct Reply {
+        status: u16,
+        cookie: Option<String>,
+        body: String,
+        delay: Duration,
+    }
+
+    impl Reply {
+        fn json(body: Value) -> Self {
+            Self { status: 200, cookie: None, body: body.to_string(), delay: Duration::ZERO }
+        }
+
+        fn status(status: u16) -> Self {
+            Self { status, cookie: None, body: String::new(), delay: Duration::ZERO }
+        }
+    }
+
+    /// A request as the test controller saw it, `path` without the
+    /// `/wsg/api/public/<version>/` prefix.
+    struct Request<'a> {
+        method: &'a str,
+        path: &'a str,
+        cookie: Option<&'a str>,
+        body: Value,
+    }
+
+    /// Serves every connection with `respond` and returns the url. Only
+    /// enough of http for reqwest, one request per connection.
+    async fn serve<F>(respond: F) -> String
+    where
+        F: Fn(Request) -> Reply + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    let mut chunk = [0; 4096];
+                    let (head, body) = loop {
+                        let n = stream.read(&mut chunk).await.unwrap_or(0);
+                        if n == 0 {
+                            return;
+                        }
+                        buffer.extend_from_slice(&chunk[..n]);
+                        let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+                        let length = header(&head, "content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+                        if buffer.len() >= end + 4 + length {
+                            break (head, buffer[end + 4..end + 4 + length].to_vec());
+                        }
+                    };
+                    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+                    let method = request_line.next().unwrap_or_default();
+                    let path = request_line.next().unwrap_or_default();
+                    let path = match path.strip_prefix("/wsg/api/public/") {
+                        Some("apiInfo") => "apiInfo",
+                        Some(rest) => rest.split_once('/').map_or(rest, |(_, path)| path),
+                        None => path,
+                    };
+                    let reply = respond(Request {
+                        method,
+                        path,
+                        cookie: header(&head, "cookie"),
+                        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+                    });
+                    tokio::time::sleep(reply.delay).await;
+                    let cookie = reply.cookie.map(|c| format!("Set-Cookie: {c}\r\n")).unwrap_or_default();
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n{cookie}\r\n{}",
+                        reply.status,
+                        reply.body.len(),
+                        reply.body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        url
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    /// Answers `apiInfo` and logins, handing out `session-1`, `session-2`, ...
+    /// and counting them in `logins`.
+    fn login(request: &Request, logins: &Mutex<usize>) -> Option<Reply> {
+        match (request.method, request.path) {
+            ("GET", "apiInfo") => Some(Reply::json(json!({ "apiSupportVersions": ["v11_1"] }))),
+            ("POST", "session") => {
+                let mut logins = logins.lock().unwrap();
+                *logins += 1;
+                Some(Reply { cookie: Some(format!("JSESSIONID=session-{logins}")), ..Reply::json(json!({})) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Remembers what the [`Auth`] reported.
+    #[derive(Debug, Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl Events {
+        fn count(&self, event: &str) -> usize {
+            self.0.lock().unwrap().iter().filter(|e| *e == event).count()
+        }
+    }
+
+    impl ApiObserver for Events {
+        fn relogin(&self) {
+            self.0.lock().unwrap().push("relogin".to_string());
+        }
+
+        fn truncated(&self, query: &str, total_count: usize) {
+            self.0.lock().unwrap().push(format!("truncated {query} {total_count}"));
+        }
+    }
+
+    fn auth(url: &str, events: &Arc<Events>) -> Auth {
+        Auth::builder(url, "monitor", "secret").observer(events.clone()).build().unwrap()
+    }
+
+    /// A controller whose `rkszones` accepts only the sessions in `valid`.
+    async fn zones_controller(
+        valid: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> (String, Arc<Mutex<usize>>, Arc<Mutex<usize>>) {
+        let logins = Arc::new(Mutex::new(0));
+        let zone_requests = Arc::new(Mutex::new(0));
+        let (l, z) = (logins.clone(), zone_requests.clone());
+        let url = serve(move |request| {
+            if let Some(reply) = login(&request, &l) {
+                return reply;
+            }
+            *z.lock().unwrap() += 1;
+            match request.cookie.and_then(|c| c.strip_prefix("JSESSIONID=")) {
+                Some(session) if valid(session) => Reply::json(json!({ "list": [{ "id": "z1", "name": "Main" }] })),
+                _ => Reply::status(401),
+            }
+        })
+        .await;
+        (url, logins, zone_requests)
+    }
+
+    #[tokio::test]
+    async fn renews_rejected_session_once() {
+        // The first session is expired by the time it is used
+        let (url, logins, zone_requests) = zones_controller(|session| session != "session-1").await;
+        let events = Arc::new(Events::default());
+        let auth = auth(&url, &events);
+
+        let zones = auth.get_zones().await.unwrap();
+        assert_eq!(zones[0].id, "z1");
+        assert_eq!(*logins.lock().unwrap(), 2);
+        assert_eq!(*zone_requests.lock().unwrap(), 2);
+        assert_eq!(events.count("relogin"), 1);
+
+        // The renewed session is kept
+        auth.get_zones().await.unwrap();
+        assert_eq!(*logins.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_renewed_session_is_rejected() {
+        let (url, logins, zone_requests) = zones_controller(|_| false).await;
+        let events = Arc::new(Events::default());
+        let auth = auth(&url, &events);
+
+        let err = auth.get_zones().await.unwrap_err();
+        assert!(matches!(err, SmartZoneError::Auth(_)), "{err}");
+        assert_eq!(*logins.lock().unwrap(), 2);
+        assert_eq!(*zone_requests.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn debug_redacts_the_password() {
+        let builder = Auth::builder("https://sz:8443", "monitor", "hunter2");
+        let debug = format!("{builder:?}");
+        assert!(debug.contains("monitor") && !debug.contains("hunter2"), "{debug}");
+        let debug = format!("{:?}", builder.build().unwrap());
+        assert!(debug.contains("monitor") && !debug.contains("hunter2"), "{debug}");
+    }
+
+    #[tokio::test]
+    async fn only_401_and_403_renew_the_session() {
+        let logins = Arc::new(Mutex::new(0));
+        let statuses = Arc::new(Mutex::new(vec![403, 500]));
+        let (l, s) = (logins.clone(), statuses.clone());
+        let url = serve(move |request| {
+            login(&request, &l).unwrap_or_else(|| match s.lock().unwrap().pop() {
+                Some(status) => Reply::status(status),
+                None => Reply::json(json!({ "list": [] })),
+            })
+        })
+        .await;
+        let events = Arc::new(Events::default());
+        let auth = auth(&url, &events);
+
+        let err = auth.get_zones().await.unwrap_err();
+        assert!(matches!(err, SmartZoneError::Status { status, .. } if status == 500), "{err}");
+        assert_eq!(*logins.lock().unwrap(), 1);
+
+        // 403 renews the session like 401 does
+        auth.get_zones().await.unwrap();
+        assert_eq!(*logins.lock().unwrap(), 2);
+        assert_eq!(events.count("relogin"), 1);
+    }
+
+    #[derive(Deserialize, Serialize, Default, Debug)]
+    struct Item {
+        id: usize,
+    }
+
+    impl Record for Item {}
+
+    /// A controller with `total` records that reports `total_count` and
+    /// `has_more` as given. Later pages are answered sooner, so they arrive
+    /// out of order when fetched concurrently.
+    async fn paging_controller(total: usize, total_count: usize, has_more: bool) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let logins = Arc::new(Mutex::new(0));
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let q = queries.clone();
+        let url = serve(move |request| {
+            if let Some(reply) = login(&request, &logins) {
+                return reply;
+            }
+            assert_eq!((request.method, request.path), ("POST", "query/ap"));
+            q.lock().unwrap().push(request.body.clone());
+            let page = request.body["page"].as_u64().unwrap() as usize;
+            let limit = request.body["limit"].as_u64().unwrap() as usize;
+            let list: Vec<Value> = ((page - 1) * limit..total).take(limit).map(|id| json!({ "id": id })).collect();
+            Reply {
+                delay: Duration::from_millis(20 * 10usize.saturating_sub(page) as u64),
+                ..Reply::json(json!({
+                    "totalCount": total_count,
+                    "hasMore": has_more || page * limit < total,
+                    "firstIndex": (page - 1) * limit,
+                    "list": list,
+                }))
+            }
+        })
+        .await;
+        (url, queries)
+    }
+
+    #[tokio::test]
+    async fn fetches_every_page_in_order() {
+        let (url, queries) = paging_controller(7, 7, false).await;
+        let events = Arc::new(Events::default());
+        let auth = Auth::builder(&url, "monitor", "secret")
+            .paging(2, 3)
+            .observer(events.clone())
+            .build()
+            .unwrap();
+
+        let items: Vec<Item> = auth.query_all(FilterContainer::new(), Query::Aps).await.unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), (0..7).collect::<Vec<_>>());
+
+        let queries = queries.lock().unwrap();
+        let mut pages: Vec<u64> = queries.iter().map(|q| q["page"].as_u64().unwrap()).collect();
+        pages.sort();
+        assert_eq!(pages, [1, 2, 3, 4]);
+        assert!(queries.iter().all(|q| q["limit"] == 2));
+        assert!(events.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_records_beyond_total_count() {
+        let (url, queries) = paging_controller(7, 3, true).await;
+        let events = Arc::new(Events::default());
+        let auth = Auth::builder(&url, "monitor", "secret")
+            .paging(2, 3)
+            .observer(events.clone())
+            .build()
+            .unwrap();
+
+        let items: Vec<Item> = auth.query_all(FilterContainer::new(), Query::Aps).await.unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(queries.lock().unwrap().len(), 2);
+        assert_eq!(events.count("truncated ap 3"), 1);
+    }
+
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    struct Radio {
+        name: String,
+        channel: u32,
+    }
+
+    impl Default for Radio {
+        fn default() -> Self {
+            Self { name: "unknown".to_string(), channel: 1 }
+        }
+    }
+
+    impl Record for Radio {}
+
+    fn radios() -> QueryResults<Value> {
+        QueryResults {
+            total_count: 3,
+            has_more: false,
+            first_index: 0,
+            list: vec![
+                json!({"name": "wlan0", "channel": 36}),
+                json!({"name": "wlan1", "channel": null}),
+                json!({"channel": 6}),
+            ],
+        }
+    }
+
+    #[test]
+    fn strict_decoding_skips_incomplete_records() {
+        let (decoded, failed) = radios().adapt::<Radio>(ApiVersion::V11_1, "query/ap page 1", false, &[]);
+        assert_eq!(decoded, [Radio { name: "wlan0".to_string(), channel: 36 }]);
+        let paths: Vec<_> = failed.iter().map(|(err, _)| match err {
+            SmartZoneError::Decode { path, .. } => path.as_str(),
+            other => panic!("{other}"),
+        }).collect();
+        assert_eq!(paths, ["list[1].channel", "list[2]"]);
+        assert_eq!(failed[1].1, json!({"channel": 6}));
+    }
+
+    #[test]
+    fn lenient_decoding_falls_back_to_defaults() {
+        let (decoded, failed) = radios().adapt::<Radio>(ApiVersion::V11_1, "query/ap page 1", true, &[]);
+        assert!(failed.is_empty());
+        assert_eq!(decoded, [
+            Radio { name: "wlan0".to_string(), channel: 36 },
+            Radio { name: "wlan1".to_string(), channel: 1 },
+            Radio { name: "unknown".to_string(), channel: 6 },
+        ]);
+    }
+
+    #[test]
+    fn requested_attributes_are_decoded_strictly() {
+        let attributes = ["channel".to_string()];
+        let (decoded, failed) = radios().adapt::<Radio>(ApiVersion::V11_1, "query/ap page 1", false, &attributes);
+        // `name` was not asked for, so it is missing on purpose
+        assert_eq!(decoded, [
+            Radio { name: "wlan0".to_string(), channel: 36 },
+            Radio { name: "unknown".to_string(), channel: 6 },
+        ]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].1, json!({"name": "wlan1", "channel": null}));
+
+        let (decoded, failed) = radios().adapt::<Radio>(ApiVersion::V11_1, "query/ap page 1", true, &attributes);
+        assert_eq!(decoded.len(), 3);
+        assert!(failed.is_empty());
+    }
+}
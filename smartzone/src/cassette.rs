@@ -0,0 +1,322 @@
+//! Recording a controller's traffic to disk and serving it back in place of
+//! the controller.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use reqwest::{
+    header::{CONTENT_TYPE, SET_COOKIE},
+    Request, Response, ResponseBuilderExt,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::SmartZoneError;
+
+/// Request body fields that are replaced before anything is written or matched.
+const SCRUBBED_FIELDS: &[&str] = &["username", "password"];
+const SCRUBBED: &str = "scrubbed";
+/// Interactions in a cassette directory before recording stops, a few poll
+/// cycles of a large site. Anything after that goes to the controller without
+/// being written.
+pub const MAX_RECORDED: usize = 1000;
+
+/// Whether a controller's traffic is written to a cassette or served from one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CassetteMode {
+    /// Talks to the controller and writes every request and response to the directory.
+    Record(PathBuf),
+    /// Never touches the network and answers from what was recorded in the directory.
+    Replay(PathBuf),
+}
+
+/// One request and the response the controller gave to it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Interaction {
+    method: String,
+    /// Path and query below the controller url, e.g. `/wsg/api/public/v11_1/query/ap`.
+    path: String,
+    /// Json bodies are kept as json so they are readable in the file.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    request: Value,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// The session cookie's name, its value is not kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    set_cookie: Option<String>,
+    body: String,
+}
+
+impl Interaction {
+    fn key(&self) -> String {
+        format!("{} {} {}", self.method, self.path, self.request)
+    }
+}
+
+/// Reads and writes the request and response pairs of one controller.
+///
+/// Credentials in request bodies and session cookies are scrubbed, but the
+/// responses are kept as they are, so a cassette holds whatever client
+/// data the controller returned.
+#[derive(Debug)]
+pub struct Cassette {
+    url: String,
+    mode: CassetteMode,
+    /// Recorded responses by request, and how many of each were replayed.
+    replay: Mutex<HashMap<String, (Vec<Interaction>, usize)>>,
+    recorded: Mutex<usize>,
+    /// How many interactions are written, [`MAX_RECORDED`] outside of tests.
+    limit: usize,
+}
+
+impl Cassette {
+    /// Loads the recorded interactions in replay mode, or creates the
+    /// directory in record mode. Recording into a directory that already has
+    /// a recording, e.g. after a config reload, continues after its last file.
+    pub fn open(url: &str, mode: CassetteMode) -> Result<Self, SmartZoneError> {
+        let mut replay: HashMap<String, (Vec<Interaction>, usize)> = HashMap::new();
+        let mut recorded = 0;
+        match &mode {
+            CassetteMode::Record(dir) => {
+                fs::create_dir_all(dir).map_err(|err| cassette_error(dir, err))?;
+                recorded = last_recorded(dir)?;
+            }
+            CassetteMode::Replay(dir) => {
+                let mut files: Vec<PathBuf> = fs::read_dir(dir)
+                    .map_err(|err| cassette_error(dir, err))?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                    .collect();
+                // Recorded in order, so requests made more than once get their
+                // responses back in the order they came
+                files.sort();
+                for file in files {
+                    let text = fs::read_to_string(&file).map_err(|err| cassette_error(&file, err))?;
+                    let interaction: Interaction =
+                        serde_json::from_str(&text).map_err(|err| cassette_error(&file, err))?;
+                    replay.entry(interaction.key()).or_default().0.push(interaction);
+                }
+            }
+        }
+        Ok(Self {
+            url: url.to_string(),
+            mode,
+            replay: Mutex::new(replay),
+            recorded: Mutex::new(recorded),
+            limit: MAX_RECORDED,
+        })
+    }
+
+    /// Whether responses come from the cassette instead of the controller.
+    pub fn is_replay(&self) -> bool {
+        matches!(self.mode, CassetteMode::Replay(_))
+    }
+
+    /// The recorded response to `request`. A request made more often than it
+    /// was recorded gets the last response again.
+    pub fn replay(&self, request: &Request) -> Result<Response, SmartZoneError> {
+        let key = self.interaction(request).key();
+        let mut replay = self.replay.lock().unwrap();
+        let (interactions, played) = replay
+            .get_mut(&key)
+            .ok_or_else(|| SmartZoneError::Cassette(format!("nothing was recorded for {key}")))?;
+        let interaction = &interactions[(*played).min(interactions.len() - 1)];
+        *played += 1;
+        Ok(self.response(request, interaction))
+    }
+
+    /// Writes `request` and its response to the next file and hands back an
+    /// equivalent response, the original body can only be read once. Once
+    /// [`MAX_RECORDED`] interactions are written the response is handed back
+    /// untouched.
+    pub async fn record(&self, request: &Request, response: Response) -> Result<Response, SmartZoneError> {
+        let CassetteMode::Record(dir) = &self.mode else {
+            return Ok(response);
+        };
+        if *self.recorded.lock().unwrap() >= self.limit {
+            return Ok(response);
+        }
+        let mut interaction = self.interaction(request);
+        interaction.status = response.status().as_u16();
+        interaction.content_type = header(&response, CONTENT_TYPE.as_str());
+        interaction.set_cookie = header(&response, SET_COOKIE.as_str())
+            .and_then(|cookie| cookie.split_once('=').map(|(name, _)| name.to_string()));
+        let mut live = http::Response::builder().status(response.status()).url(response.url().clone());
+        for (name, value) in response.headers() {
+            live = live.header(name, value);
+        }
+        interaction.body = response.text().await?;
+
+        let file = {
+            let mut recorded = self.recorded.lock().unwrap();
+            // Requests running at the same time can all get past the check above
+            if *recorded >= self.limit {
+                return Ok(live.body(interaction.body).unwrap().into());
+            }
+            *recorded += 1;
+            let endpoint = interaction.path.rsplit('/').next().unwrap_or_default().to_string();
+            dir.join(format!("{:05}-{}-{endpoint}.json", *recorded, interaction.method.to_lowercase()))
+        };
+        let json = serde_json::to_string_pretty(&interaction).unwrap();
+        fs::write(&file, json).map_err(|err| cassette_error(&file, err))?;
+        // The caller still needs the real session cookie
+        Ok(live.body(interaction.body).unwrap().into())
+    }
+
+    /// The scrubbed request half of an interaction.
+    fn interaction(&self, request: &Request) -> Interaction {
+        let url = request.url().as_str();
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+            .unwrap_or_default();
+        let request_body = match serde_json::from_str::<Value>(&body) {
+            Ok(Value::Object(mut fields)) => {
+                for field in SCRUBBED_FIELDS {
+                    if let Some(value) = fields.get_mut(*field) {
+                        *value = Value::from(SCRUBBED);
+                    }
+                }
+                Value::Object(fields)
+            }
+            Ok(json) => json,
+            Err(_) if body.is_empty() => Value::Null,
+            Err(_) => Value::from(body),
+        };
+        Interaction {
+            method: request.method().to_string(),
+            path: url.strip_prefix(&self.url).unwrap_or(url).to_string(),
+            request: request_body,
+            status: 0,
+            content_type: None,
+            set_cookie: None,
+            body: String::new(),
+        }
+    }
+
+    fn response(&self, request: &Request, interaction: &Interaction) -> Response {
+        let mut builder = http::Response::builder().status(interaction.status).url(request.url().clone());
+        if let Some(content_type) = &interaction.content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        if let Some(name) = &interaction.set_cookie {
+            builder = builder.header(SET_COOKIE, format!("{name}={SCRUBBED}"));
+        }
+        builder.body(interaction.body.clone()).unwrap().into()
+    }
+}
+
+/// Number of the last interaction written to `dir`, 0 if there is none.
+fn last_recorded(dir: &Path) -> Result<usize, SmartZoneError> {
+    let last = fs::read_dir(dir)
+        .map_err(|err| cassette_error(dir, err))?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            let (number, _) = name.to_str()?.split_once('-')?;
+            number.parse::<usize>().ok()
+        })
+        .max();
+    Ok(last.unwrap_or(0))
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(String::from)
+}
+
+fn cassette_error(path: &Path, err: impl std::fmt::Display) -> SmartZoneError {
+    SmartZoneError::Cassette(format!("{}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cassette-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn login(password: &str) -> Request {
+        reqwest::Client::new()
+            .post("https://sz.example.org:8443/wsg/api/public/v11_1/session")
+            .body(format!(r#"{{"username":"admin","password":"{password}","timeZoneUtcOffset":"-07:00"}}"#))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_scrubbed_recording() {
+        let dir = dir("replay");
+        let recorder = Cassette::open("https://sz.example.org:8443", CassetteMode::Record(dir.clone())).unwrap();
+        let response: Response = http::Response::builder()
+            .status(200)
+            .header(SET_COOKIE, "JSESSIONID=very-secret; Path=/wsg")
+            .body("{}")
+            .unwrap()
+            .into();
+        let response = recorder.record(&login("hunter2"), response).await.unwrap();
+        assert_eq!(response.headers()[SET_COOKIE], "JSESSIONID=very-secret; Path=/wsg");
+
+        let written = fs::read_to_string(fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path()).unwrap();
+        assert!(!written.contains("hunter2"));
+        assert!(!written.contains("admin"));
+        assert!(!written.contains("very-secret"));
+
+        // The password does not have to match to replay a login
+        let player = Cassette::open("https://sz.example.org:8443", CassetteMode::Replay(dir.clone())).unwrap();
+        let replayed = player.replay(&login("other")).unwrap();
+        assert_eq!(replayed.status(), 200);
+        assert_eq!(replayed.headers()[SET_COOKIE], "JSESSIONID=scrubbed");
+        assert_eq!(replayed.text().await.unwrap(), "{}");
+
+        let unknown = reqwest::Client::new()
+            .get("https://sz.example.org:8443/wsg/api/public/v11_1/rkszones")
+            .build()
+            .unwrap();
+        assert!(player.replay(&unknown).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_recording_at_the_limit() {
+        let dir = dir("limit");
+        let mut recorder = Cassette::open("https://sz.example.org:8443", CassetteMode::Record(dir.clone())).unwrap();
+        recorder.limit = 2;
+        for n in 0..3 {
+            let response: Response = http::Response::builder().status(200).body(format!("{{\"n\":{n}}}")).unwrap().into();
+            let response = recorder.record(&login("hunter2"), response).await.unwrap();
+            // Past the limit the controller's answer still gets through
+            assert_eq!(response.text().await.unwrap(), format!("{{\"n\":{n}}}"));
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn continues_an_earlier_recording() {
+        let dir = dir("continue");
+        for n in 0..2 {
+            let recorder = Cassette::open("https://sz.example.org:8443", CassetteMode::Record(dir.clone())).unwrap();
+            let response: Response = http::Response::builder().status(200).body(format!("{{\"n\":{n}}}")).unwrap().into();
+            recorder.record(&login("hunter2"), response).await.unwrap();
+        }
+        let mut files: Vec<_> = fs::read_dir(&dir).unwrap().map(|f| f.unwrap().file_name()).collect();
+        files.sort();
+        assert_eq!(files, ["00001-post-session.json", "00002-post-session.json"]);
+
+        // Replayed in the order they were recorded
+        let player = Cassette::open("https://sz.example.org:8443", CassetteMode::Replay(dir.clone())).unwrap();
+        for n in 0..2 {
+            let replayed = player.replay(&login("hunter2")).unwrap();
+            assert_eq!(replayed.text().await.unwrap(), format!("{{\"n\":{n}}}"));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
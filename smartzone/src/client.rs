@@ -1,101 +1,147 @@
-use serde::Deserialize;
-use crate::smartzone::*;
+//! Wireless clients as returned by `query/client`.
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
+use serde::{Deserialize, Serialize};
+use crate::api::*;
+
+/// A wireless client as seen by the AP it is connected to.
+///
+/// Counters are per session and start over when the client reconnects.
+#[derive(Deserialize, Serialize, Default)]
 pub struct Client {
+    /// MAC of the AP the client is connected to.
     #[serde(rename = "apMac")]
     pub ap_mac: String,
+    /// Name of the AP the client is connected to.
     #[serde(rename = "apName")]
     pub ap_name: String,
+    /// Hostname the client reported, empty if unknown.
     #[serde(rename = "hostname")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub hostname: String,
+    /// Connection status.
     #[serde(rename = "status")]
     pub status: String,
+    /// Whether the client passed authentication.
     #[serde(rename = "authStatus")]
     pub auth_status: String,
+    /// Number of open alerts for the client.
     #[serde(rename = "alerts")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub alerts: u64,
+    /// Operating system, e.g. `Chrome OS`.
     #[serde(rename = "osType")]
     pub os_type: String,
+    /// Kind of device.
     #[serde(rename = "deviceType")]
     pub device_type: String,
+    /// Vendor of the operating system.
     #[serde(rename = "osVendorType")]
     pub os_vendor_type: String,
+    /// Device model, if the controller could tell.
     #[serde(rename = "modelName")]
     pub model_name: String,
+    /// IPv4 address.
     #[serde(rename = "ipAddress")]
     pub ip_address: String,
+    /// IPv6 address.
     #[serde(rename = "ipv6Address")]
     pub ipv6_address: String,
+    /// The client's MAC, what it is keyed by.
     #[serde(rename = "clientMac")]
     pub client_mac: String,
+    /// SSID the client is connected to.
     #[serde(rename = "ssid")]
     pub ssid: String,
+    /// Bytes sent to the client this session.
     #[serde(rename = "txBytes")]
     pub tx_bytes: u64,
+    /// Bytes received from the client this session.
     #[serde(rename = "rxBytes")]
     pub rx_bytes: u64,
+    /// `tx_bytes` and `rx_bytes` together.
     #[serde(rename = "txRxBytes")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_rx_bytes: u64,
+    /// Bytes received from the client in the last sampling interval.
     #[serde(rename = "uplink")]
     pub uplink: u64,
+    /// Bytes sent to the client in the last sampling interval.
     #[serde(rename = "downlink")]
     pub downlink: u64,
+    /// `uplink` and `downlink` together.
     #[serde(rename = "traffic")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub traffic: u64,
+    /// Uplink rate in bits per second.
     #[serde(rename = "uplinkRate")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub uplink_rate: u64,
+    /// Downlink rate in bits per second.
     #[serde(rename = "downlinkRate")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub downlink_rate: u64,
+    /// Received signal strength in dBm.
     #[serde(rename = "rssi")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub rssi: i64,
+    /// Signal to noise ratio in dB.
     #[serde(rename = "snr")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub snr: i64,
+    /// 802.11 mode of the connection, e.g. `11ac`.
     #[serde(rename = "radioType")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub radio_type: String,
+    /// VLAN the client's traffic is put on.
     #[serde(rename = "vlan")]
     pub vlan: u64,
+    /// Channel the client is connected on.
     #[serde(rename = "channel")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub channel: u64,
+    /// User the client authenticated as, empty for open and PSK networks.
     #[serde(rename = "userName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub user_name: String,
+    /// How the client authenticated.
     #[serde(rename = "authMethod")]
     pub auth_method: String,
+    /// Encryption of the connection.
     #[serde(rename = "encryptionMethod")]
     pub encryption_method: String,
+    /// Control plane the AP is managed by.
     #[serde(rename = "controlPlaneName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub control_plane_name: String,
+    /// Data plane the client's traffic is tunnelled through, if any.
     #[serde(rename = "dataPlaneName")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub data_plane_name: String,
+    /// Frames received from the client this session.
     #[serde(rename = "rxFrames")]
     pub rx_frames: u64,
+    /// Frames sent to the client this session.
     #[serde(rename = "txFrames")]
     pub tx_frames: u64,
+    /// Data frames to the client that were dropped.
     #[serde(rename = "txDropDataFrames")]
     #[serde(deserialize_with = "deserialize_null_default")]
     pub tx_drop_data_frames: u64,
+    /// When the session started, in milliseconds since the epoch.
     #[serde(rename = "sessionStartTime")]
     pub session_start_time: u64,
+    /// Result of the last SpeedFlex throughput test.
     #[serde(rename = "speedflex")]
     pub speedflex: u64,
+    /// Kind of WLAN.
     #[serde(rename = "wlanType")]
     pub wlan_type: String,
+    /// Id of the client's zone.
     #[serde(rename = "zoneId")]
     pub zone_id: String,
+    /// Firmware version of the client's zone.
     #[serde(rename = "zoneVersion")]
     pub zone_version: String,
 }
+
+impl Record for Client {}
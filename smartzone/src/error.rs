@@ -0,0 +1,85 @@
+//! The error type of the crate.
+
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// Everything that can go wrong while talking to a smartzone controller.
+#[derive(Debug)]
+pub enum SmartZoneError {
+    /// The request never got a response (dns, tls, connection reset, ...).
+    Transport(reqwest::Error),
+    /// The controller answered with a non success status code.
+    Status {
+        /// The url that was requested.
+        url: String,
+        /// What the controller answered with.
+        status: StatusCode,
+    },
+    /// Logging in failed or the session was rejected even after renewing it.
+    Auth(String),
+    /// The response body did not match the expected shape.
+    Decode {
+        /// What was being decoded.
+        context: String,
+        /// Where in the body decoding failed, e.g. `list[3].rssi`. Empty if
+        /// it failed at the top.
+        path: String,
+        /// The error serde gave.
+        source: serde_json::Error,
+    },
+    /// A request body could not be turned into json.
+    Encode {
+        /// What was being encoded.
+        context: String,
+        /// The error serde gave.
+        source: serde_json::Error,
+    },
+    /// The tls settings could not be turned into a client config.
+    Tls(String),
+    /// The controller does not offer an api version this client speaks.
+    ApiVersion {
+        /// The controller that was asked.
+        url: String,
+        /// The versions the controller offered.
+        supported: Vec<String>,
+    },
+    /// Traffic could not be recorded, or a request has no recorded response.
+    Cassette(String),
+}
+
+impl fmt::Display for SmartZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(err) => write!(f, "request to smartzone failed: {err}"),
+            Self::Status { url, status } => write!(f, "{url} returned {status}"),
+            Self::Auth(reason) => write!(f, "authentication failed: {reason}"),
+            Self::Decode { context, path, source } if path.is_empty() => write!(f, "failed to decode {context}: {source}"),
+            Self::Decode { context, path, source } => write!(f, "failed to decode {context} at {path}: {source}"),
+            Self::Encode { context, source } => write!(f, "failed to encode {context}: {source}"),
+            Self::Tls(reason) => write!(f, "invalid tls settings: {reason}"),
+            Self::ApiVersion { url, supported } => write!(
+                f,
+                "no usable api version in common with {url} (it supports {})",
+                supported.join(", ")
+            ),
+            Self::Cassette(reason) => write!(f, "cassette: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SmartZoneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(err) => Some(err),
+            Self::Decode { source, .. } | Self::Encode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SmartZoneError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Transport(err)
+    }
+}
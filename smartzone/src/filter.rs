@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{Zone, DEFAULT_PAGE_SIZE},
+    ap::AP,
+    client::Client,
+};
+
+/// The body of a query, built up like
+///
+/// ```
+/// use smartzone::{Filter, FilterContainer, SortDirection};
+///
+/// let filter = FilterContainer::new()
+///     .filter(Filter::zone("2b5f0e1c"))
+///     .and(Filter::ssid("Students"))
+///     .not(Filter::os_type("Android"))
+///     .search("chromebook", ["hostname"])
+///     .sort("hostname", SortDirection::Asc)
+///     .attributes(["clientMac", "hostname"]);
+/// ```
+///
+/// A record is returned if it matches one of `filters`, all of
+/// `extraFilters` and none of `extraNotFilters`.
+#[derive(Serialize, Debug, Clone)]
+pub struct FilterContainer {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    /// Alternatives, see [`FilterContainer::filter`].
+    #[serde(rename = "filters")]
+    pub or: Vec<Filter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    /// Filters every record matches, see [`FilterContainer::and`].
+    #[serde(rename = "extraFilters")]
+    pub and: Vec<Filter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    /// Filters no record matches, see [`FilterContainer::not`].
+    #[serde(rename = "extraNotFilters")]
+    pub not: Vec<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Set by [`FilterContainer::search`] and [`FilterContainer::search_any`].
+    #[serde(rename = "fullTextSearch")]
+    pub full_text_search: Option<FullTextSearch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Set by [`FilterContainer::sort`].
+    #[serde(rename = "sortInfo")]
+    pub sort_info: Option<SortInfo>,
+    /// The fields the controller sends for each record, every field if empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<String>,
+    /// The page to return, counting from 1.
+    pub page: usize,
+    /// Records per page.
+    pub limit: usize,
+}
+
+impl Default for FilterContainer {
+    fn default() -> Self {
+        Self {
+            or: Vec::new(),
+            and: Vec::new(),
+            not: Vec::new(),
+            full_text_search: None,
+            sort_info: None,
+            attributes: Vec::new(),
+            page: 1,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl FilterContainer {
+    /// Matches every record, the first page of [`DEFAULT_PAGE_SIZE`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an alternative, records matching any of these are returned.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.or.push(filter);
+        self
+    }
+
+    /// Adds a filter every record has to match.
+    pub fn and(mut self, filter: Filter) -> Self {
+        self.and.push(filter);
+        self
+    }
+
+    /// Adds a filter no record may match.
+    pub fn not(mut self, filter: Filter) -> Self {
+        self.not.push(filter);
+        self
+    }
+
+    /// Only records with every word of `text` in one of `fields`, or in any
+    /// field if `fields` is empty.
+    pub fn search<S: Into<String>>(self, text: impl Into<String>, fields: impl IntoIterator<Item = S>) -> Self {
+        self.full_text_search("AND", text.into(), fields)
+    }
+
+    /// Like [`FilterContainer::search`], but one of the words of `text` is
+    /// enough.
+    pub fn search_any<S: Into<String>>(self, text: impl Into<String>, fields: impl IntoIterator<Item = S>) -> Self {
+        self.full_text_search("OR", text.into(), fields)
+    }
+
+    fn full_text_search<S: Into<String>>(
+        mut self,
+        ttype: &str,
+        value: String,
+        fields: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.full_text_search = Some(FullTextSearch {
+            ttype: ttype.to_string(),
+            value,
+            fields: fields.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    /// Orders the records by `column`, a json field name.
+    pub fn sort(mut self, column: impl Into<String>, dir: SortDirection) -> Self {
+        self.sort_info = Some(SortInfo { sort_column: column.into(), dir });
+        self
+    }
+
+    /// Asks for only these fields, by their json names. Fields left out
+    /// decode as their defaults, like with lenient decoding.
+    pub fn attributes<S: Into<String>>(mut self, attributes: impl IntoIterator<Item = S>) -> Self {
+        self.attributes = attributes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// `page` counts from 1.
+    pub fn page(mut self, page: usize) -> Self {
+        self.page = page;
+        self
+    }
+
+    /// Records per page.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl From<&Zone> for FilterContainer {
+    fn from(zone: &Zone) -> Self {
+        Self::new().filter(zone.into())
+    }
+}
+
+impl FromIterator<Filter> for FilterContainer {
+    fn from_iter<T: IntoIterator<Item = Filter>>(iter: T) -> Self {
+        Self {
+            or: iter.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// One condition of a query, e.g. everything in a zone or on an AP.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Filter {
+    #[serde(rename = "type")]
+    ttype: String,
+    value: String,
+    operator: String,
+}
+
+impl Filter {
+    /// Records where `ttype` equals `value`, for the types without a
+    /// constructor of their own.
+    pub fn new(ttype: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            ttype: ttype.into(),
+            value: value.into(),
+            operator: "eq".to_string(),
+        }
+    }
+
+    /// Compares with `operator` instead of `eq`.
+    pub fn operator(mut self, operator: impl Into<String>) -> Self {
+        self.operator = operator.into();
+        self
+    }
+
+    /// Records in the zone with this id.
+    pub fn zone(id: impl Into<String>) -> Self {
+        Self::new("ZONE", id)
+    }
+
+    /// Records in the AP group with this id.
+    pub fn ap_group(id: impl Into<String>) -> Self {
+        Self::new("APGROUP", id)
+    }
+
+    /// Records of the AP with this MAC.
+    pub fn ap(mac: impl Into<String>) -> Self {
+        Self::new("AP", mac)
+    }
+
+    /// Clients connected to this SSID.
+    pub fn ssid(ssid: impl Into<String>) -> Self {
+        Self::new("SSID", ssid)
+    }
+
+    /// Clients running this operating system, e.g. `Android`.
+    pub fn os_type(os_type: impl Into<String>) -> Self {
+        Self::new("OS_TYPE", os_type)
+    }
+
+    /// AP status, e.g. `Online`.
+    pub fn status(status: impl Into<String>) -> Self {
+        Self::new("STATUS", status)
+    }
+
+    /// What is compared, e.g. `ZONE`.
+    pub fn ttype(&self) -> &str {
+        &self.ttype
+    }
+
+    /// What it is compared with.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl From<&Zone> for Filter {
+    fn from(zone: &Zone) -> Self {
+        Self::zone(&zone.id)
+    }
+}
+
+/// Anything that names an AP, so a query can be narrowed down to it.
+pub trait ApMac {
+    /// The AP's MAC, e.g. `AA:BB:CC:00:11:22`.
+    fn ap_mac(&self) -> &str;
+}
+
+impl<T: ApMac> From<&T> for Filter {
+    fn from(value: &T) -> Self {
+        Self::ap(value.ap_mac())
+    }
+}
+
+/// Just the MAC of an AP.
+#[derive(Deserialize, Debug)]
+pub struct MiniAp {
+    /// The AP's MAC.
+    #[serde(rename = "apMac")]
+    pub ap_mac: String,
+}
+
+impl ApMac for MiniAp {
+    fn ap_mac(&self) -> &str {
+        &self.ap_mac
+    }
+}
+
+impl ApMac for AP {
+    fn ap_mac(&self) -> &str {
+        &self.ap_mac
+    }
+}
+
+/// The AP the client is connected to.
+impl ApMac for Client {
+    fn ap_mac(&self) -> &str {
+        &self.ap_mac
+    }
+}
+
+/// Words to look for in the records' fields.
+#[derive(Serialize, Debug, Clone)]
+pub struct FullTextSearch {
+    /// How the words of `value` are combined, `AND` or `OR`.
+    #[serde(rename = "type")]
+    pub ttype: String,
+    /// The words, separated by spaces.
+    pub value: String,
+    /// The json fields searched, every field if empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+/// The order records are returned in.
+#[derive(Serialize, Debug, Clone)]
+pub struct SortInfo {
+    /// The json field sorted by.
+    #[serde(rename = "sortColumn")]
+    pub sort_column: String,
+    /// Ascending or descending.
+    pub dir: SortDirection,
+}
+
+/// See [`SortInfo`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum SortDirection {
+    /// Smallest first.
+    #[serde(rename = "ASC")]
+    Asc,
+    /// Largest first.
+    #[serde(rename = "DESC")]
+    Desc,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn zone_query_stays_minimal() {
+        let zone = Zone { id: "z1".to_string(), name: "Main".to_string() };
+        let body = serde_json::to_value(FilterContainer::from(&zone)).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "filters": [{ "type": "ZONE", "value": "z1", "operator": "eq" }],
+                "page": 1,
+                "limit": DEFAULT_PAGE_SIZE,
+            })
+        );
+    }
+
+    #[test]
+    fn builds_every_section() {
+        let ap = MiniAp { ap_mac: "AA:00:00:00:00:01".to_string() };
+        let filter = FilterContainer::new()
+            .filter(Filter::zone("z1"))
+            .filter(Filter::zone("z2"))
+            .and(Filter::ap_group("g1"))
+            .and((&ap).into())
+            .not(Filter::status("Offline").operator("ne"))
+            .search("chrome", ["hostname", "modelName"])
+            .sort("hostname", SortDirection::Desc)
+            .attributes(["clientMac", "hostname"])
+            .page(3)
+            .limit(50);
+        let body = serde_json::to_value(filter).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "filters": [
+                    { "type": "ZONE", "value": "z1", "operator": "eq" },
+                    { "type": "ZONE", "value": "z2", "operator": "eq" },
+                ],
+                "extraFilters": [
+                    { "type": "APGROUP", "value": "g1", "operator": "eq" },
+                    { "type": "AP", "value": "AA:00:00:00:00:01", "operator": "eq" },
+                ],
+                "extraNotFilters": [{ "type": "STATUS", "value": "Offline", "operator": "ne" }],
+                "fullTextSearch": { "type": "AND", "value": "chrome", "fields": ["hostname", "modelName"] },
+                "sortInfo": { "sortColumn": "hostname", "dir": "DESC" },
+                "attributes": ["clientMac", "hostname"],
+                "page": 3,
+                "limit": 50,
+            })
+        );
+    }
+
+    #[test]
+    fn search_any_matches_one_of_the_words() {
+        let filter = FilterContainer::new().search_any("chromebook ipad", Vec::<String>::new());
+        let body = serde_json::to_value(filter).unwrap();
+        assert_eq!(body["fullTextSearch"], json!({ "type": "OR", "value": "chromebook ipad" }));
+    }
+}
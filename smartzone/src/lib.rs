@@ -0,0 +1,45 @@
+//! Client for the public api of Ruckus SmartZone controllers.
+//!
+//! An [`Auth`] is a session with one controller. It negotiates the api
+//! version, logs in on the first request, renews the session when the
+//! controller expires it and pages through queries:
+//!
+//! ```no_run
+//! use smartzone::{Auth, Filter, FilterContainer, Query, AP};
+//!
+//! # async fn run() -> Result<(), smartzone::SmartZoneError> {
+//! let auth = Auth::builder("https://10.0.0.1:8443", "monitor", "secret")
+//!     .paging(200, 4)
+//!     .build()?;
+//! for zone in auth.get_zones().await? {
+//!     let online = FilterContainer::from(&zone)
+//!         .and(Filter::status("Online"))
+//!         .attributes(["apMac", "deviceName"]);
+//!     let aps: Vec<AP> = auth.query_all(online, Query::Aps).await?;
+//!     println!("{}: {} APs online", zone.name, aps.len());
+//! }
+//! auth.logout().await
+//! # }
+//! ```
+//!
+//! Nothing is read from the environment, everything is set on the
+//! [`AuthBuilder`].
+#![warn(missing_docs)]
+
+pub mod ap;
+pub mod cassette;
+pub mod client;
+pub mod error;
+mod filter;
+pub mod tls;
+pub mod version;
+mod api;
+
+pub use ap::AP;
+pub use api::{
+    ApiObserver, Auth, AuthBuilder, Query, QueryResults, Record, SmartZoneApi, Zone, DEFAULT_PAGE_CONCURRENCY,
+    DEFAULT_PAGE_SIZE, DEFAULT_TIME_ZONE,
+};
+pub use client::Client;
+pub use error::SmartZoneError;
+pub use filter::{ApMac, Filter, FilterContainer, FullTextSearch, MiniAp, SortDirection, SortInfo};
@@ -0,0 +1,364 @@
+//! Certificate verification and client certificates for the connection to
+//! a controller.
+
+use std::{fs::File, io::BufReader, path::PathBuf, sync::Arc};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{api::ApiObserver, error::SmartZoneError};
+
+/// How the controller's certificate is checked.
+///
+/// By default the system trust store is used. A CA bundle replaces the trust
+/// store, a pinned fingerprint replaces chain validation entirely, and
+/// insecure mode accepts anything and has to be asked for explicitly.
+#[derive(Debug, Default, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the CA certificates to trust instead of the system store.
+    pub ca_file: Option<PathBuf>,
+    /// SHA-256 fingerprint of the controller's leaf certificate.
+    pub pin_sha256: Option<[u8; 32]>,
+    /// PEM certificate chain presented to the controller.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key of `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// Skip certificate verification completely.
+    pub insecure: bool,
+}
+
+impl TlsConfig {
+    /// Rejects combinations that contradict each other, such as insecure
+    /// mode with a pinned certificate, or a client certificate without a key.
+    pub fn validate(&self) -> Result<(), SmartZoneError> {
+        if self.insecure && (self.pin_sha256.is_some() || self.ca_file.is_some()) {
+            return Err(SmartZoneError::Tls(
+                "insecure mode can not be combined with a CA file or pinned certificate".to_string(),
+            ));
+        }
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err(SmartZoneError::Tls(
+                "a client certificate needs both a cert and a key file".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// System certificates that can not be loaded are reported to `observer`
+    /// and left out.
+    pub fn client_config(&self, observer: &dyn ApiObserver) -> Result<ClientConfig, SmartZoneError> {
+        self.validate()?;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| SmartZoneError::Tls(err.to_string()))?;
+
+        let builder = if self.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { pin: None, provider }))
+        } else if let Some(pin) = self.pin_sha256 {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { pin: Some(pin), provider }))
+        } else {
+            builder.with_root_certificates(self.roots(observer)?)
+        };
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+                .map_err(|err| SmartZoneError::Tls(format!("invalid client certificate: {err}"))),
+            _ => Ok(builder.with_no_client_auth()),
+        }
+    }
+
+    fn roots(&self, observer: &dyn ApiObserver) -> Result<RootCertStore, SmartZoneError> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca_file {
+            Some(path) => {
+                for cert in read_certs(path)? {
+                    roots.add(cert).map_err(|err| {
+                        SmartZoneError::Tls(format!("invalid CA in {}: {err}", path.display()))
+                    })?;
+                }
+            }
+            None => {
+                let native = rustls_native_certs::load_native_certs();
+                for err in native.errors {
+                    observer.certificate_skipped(&err.to_string());
+                }
+                roots.add_parsable_certificates(native.certs);
+            }
+        }
+        Ok(roots)
+    }
+}
+
+/// Accepts the controller's certificate if its fingerprint matches the pin,
+/// or any certificate when there is no pin. Handshake signatures are still
+/// checked so the server has to own the key for the certificate it sends.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    pin: Option<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.pin {
+            Some(pin) if Sha256::digest(end_entity.as_ref()).as_slice() != pin => Err(
+                rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure),
+            ),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Accepts hex with or without `:` separators, as printed by openssl.
+pub fn parse_fingerprint(value: &str) -> Result<[u8; 32], SmartZoneError> {
+    let hex: String = value.chars().filter(|c| *c != ':').collect();
+    let invalid = || SmartZoneError::Tls(format!("{value} is not a SHA-256 fingerprint"));
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut pin = [0; 32];
+    for (i, byte) in pin.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(pin)
+}
+
+fn open(path: &PathBuf) -> Result<BufReader<File>, SmartZoneError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| SmartZoneError::Tls(format!("failed to open {}: {err}", path.display())))
+}
+
+fn read_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>, SmartZoneError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| SmartZoneError::Tls(format!("failed to read {}: {err}", path.display())))?;
+    if certs.is_empty() {
+        return Err(SmartZoneError::Tls(format!("no certificates in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>, SmartZoneError> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|err| SmartZoneError::Tls(format!("failed to read {}: {err}", path.display())))?
+        .ok_or_else(|| SmartZoneError::Tls(format!("no private key in {}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::NoObserver;
+    use rcgen::{CertificateParams, CertifiedKey, KeyPair};
+    use rustls::{server::WebPkiClientVerifier, ServerConfig};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_rustls::TlsAcceptor;
+
+    struct Pem {
+        dir: PathBuf,
+    }
+
+    impl Pem {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("ruckus_metrics_tls_{name}_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self { dir }
+        }
+
+        fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Pem {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn self_signed(name: &str) -> CertifiedKey {
+        rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap()
+    }
+
+    /// Serves a single https response per connection and returns the port.
+    async fn serve(server: CertifiedKey, client_ca: Option<&CertifiedKey>) -> u16 {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = match client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                roots.add(ca.cert.der().clone()).unwrap();
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .unwrap();
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder
+            .with_single_cert(
+                vec![server.cert.der().clone()],
+                PrivateKeyDer::try_from(server.key_pair.serialize_der()).unwrap(),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = [0; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                        .await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn connects(config: &TlsConfig, port: u16) -> bool {
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(config.client_config(&NoObserver).unwrap())
+            .build()
+            .unwrap();
+        client
+            .get(format!("https://localhost:{port}/"))
+            .send()
+            .await
+            .is_ok_and(|res| res.status().is_success())
+    }
+
+    #[tokio::test]
+    async fn system_roots_reject_self_signed() {
+        let port = serve(self_signed("localhost"), None).await;
+        assert!(!connects(&TlsConfig::default(), port).await);
+    }
+
+    #[tokio::test]
+    async fn ca_file_trusts_controller() {
+        let pem = Pem::new("ca");
+        let server = self_signed("localhost");
+        let ca_file = Some(pem.write("ca.pem", &server.cert.pem()));
+        let port = serve(server, None).await;
+
+        assert!(connects(&TlsConfig { ca_file, ..Default::default() }, port).await);
+
+        let other = Some(pem.write("other.pem", &self_signed("localhost").cert.pem()));
+        assert!(!connects(&TlsConfig { ca_file: other, ..Default::default() }, port).await);
+    }
+
+    #[tokio::test]
+    async fn pinned_fingerprint() {
+        let server = self_signed("controller.invalid");
+        let fingerprint: [u8; 32] = Sha256::digest(server.cert.der()).into();
+        let port = serve(server, None).await;
+
+        assert!(connects(&TlsConfig { pin_sha256: Some(fingerprint), ..Default::default() }, port).await);
+        assert!(!connects(&TlsConfig { pin_sha256: Some([0; 32]), ..Default::default() }, port).await);
+    }
+
+    #[tokio::test]
+    async fn insecure_accepts_anything() {
+        let port = serve(self_signed("controller.invalid"), None).await;
+        assert!(connects(&TlsConfig { insecure: true, ..Default::default() }, port).await);
+    }
+
+    #[tokio::test]
+    async fn client_certificate() {
+        let pem = Pem::new("client");
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        let client_key = KeyPair::generate().unwrap();
+        let client_cert = CertificateParams::new(vec!["exporter".to_string()])
+            .unwrap()
+            .signed_by(&client_key, &ca_cert, &ca_key)
+            .unwrap();
+        let ca = CertifiedKey { cert: ca_cert, key_pair: ca_key };
+
+        let server = self_signed("localhost");
+        let ca_file = Some(pem.write("ca.pem", &server.cert.pem()));
+        let port = serve(server, Some(&ca)).await;
+
+        let without = TlsConfig { ca_file: ca_file.clone(), ..Default::default() };
+        assert!(!connects(&without, port).await);
+
+        let with = TlsConfig {
+            ca_file,
+            client_cert: Some(pem.write("client.pem", &client_cert.pem())),
+            client_key: Some(pem.write("client.key", &client_key.serialize_pem())),
+            ..Default::default()
+        };
+        assert!(connects(&with, port).await);
+    }
+
+    #[test]
+    fn fingerprint_formats() {
+        let colons = ["ab"; 32].join(":");
+        assert_eq!(parse_fingerprint(&colons).unwrap(), [0xab; 32]);
+        assert_eq!(parse_fingerprint(&"AB".repeat(32)).unwrap(), [0xab; 32]);
+        assert!(parse_fingerprint("abcd").is_err());
+    }
+
+    #[test]
+    fn insecure_conflicts_with_pin() {
+        let config = TlsConfig { insecure: true, pin_sha256: Some([0; 32]), ..Default::default() };
+        assert!(config.client_config(&NoObserver).is_err());
+    }
+}
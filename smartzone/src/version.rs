@@ -0,0 +1,137 @@
+//! Api versions and which of them this crate can decode.
+
+use std::{fmt, str::FromStr};
+
+use crate::error::SmartZoneError;
+
+/// A smartzone public api version such as `v11_1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion {
+    /// The `10` in `v10_0`.
+    pub major: u32,
+    /// The `0` in `v10_0`.
+    pub minor: u32,
+}
+
+impl ApiVersion {
+    /// The oldest version that can be decoded.
+    pub const V10_0: Self = Self::new(10, 0);
+    /// The version the records are written against.
+    pub const V11_1: Self = Self::new(11, 1);
+
+    /// The version `v{major}_{minor}`.
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// Versions the [`crate::ap::AP`] and [`crate::client::Client`] structs can be
+/// decoded from, oldest first. The structs are written against v11_1, older
+/// versions are adapted in their `Record::adapt` impls. Newer versions are
+/// only used when asked for with an override.
+pub const SUPPORTED: &[ApiVersion] = &[ApiVersion::V10_0, ApiVersion::new(11, 0), ApiVersion::V11_1];
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}_{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s} is not an api version like v11_1");
+        let (major, minor) = s
+            .strip_prefix('v')
+            .and_then(|v| v.split_once('_'))
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Picks the newest version both sides support, or checks that the
+/// override is offered by the controller.
+pub fn negotiate(
+    url: &str,
+    offered: &[String],
+    preferred: Option<ApiVersion>,
+) -> Result<ApiVersion, SmartZoneError> {
+    let offered_versions: Vec<ApiVersion> = offered.iter().filter_map(|v| v.parse().ok()).collect();
+    let chosen = match preferred {
+        Some(version) => offered_versions.contains(&version).then_some(version),
+        None => offered_versions
+            .into_iter()
+            .filter(|v| SUPPORTED.contains(v))
+            .max(),
+    };
+    chosen.ok_or_else(|| SmartZoneError::ApiVersion {
+        url: url.to_string(),
+        supported: offered.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offered(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!("v11_1".parse(), Ok(ApiVersion::V11_1));
+        assert_eq!("v13_10".parse(), Ok(ApiVersion::new(13, 10)));
+        for junk in ["11_1", "v11", "v11.1", "v_1", "vx_1", ""] {
+            assert!(junk.parse::<ApiVersion>().is_err(), "{junk} parsed");
+        }
+        assert_eq!(ApiVersion::new(10, 0).to_string(), "v10_0");
+    }
+
+    #[test]
+    fn picks_newest_common_version() {
+        let chosen = negotiate("sz", &offered(&["v9_1", "v10_0", "v11_0", "v12_0"]), None).unwrap();
+        assert_eq!(chosen, ApiVersion::new(11, 0));
+    }
+
+    #[test]
+    fn supports_v10_0_to_v11_1() {
+        let oldest = negotiate("sz", &offered(&["v9_1", "v10_0"]), None).unwrap();
+        assert_eq!(oldest, ApiVersion::V10_0);
+        let newest = negotiate("sz", &offered(&["v11_1", "v12_0", "v13_1"]), None).unwrap();
+        assert_eq!(newest, ApiVersion::V11_1);
+        assert!(negotiate("sz", &offered(&["v9_1", "v12_0"]), None).is_err());
+    }
+
+    #[test]
+    fn ignores_junk_versions() {
+        let chosen = negotiate("sz", &offered(&["latest", "v11_1", "v12", ""]), None).unwrap();
+        assert_eq!(chosen, ApiVersion::V11_1);
+    }
+
+    #[test]
+    fn override_must_be_offered() {
+        let offered = offered(&["v10_0", "v11_1", "v12_0"]);
+        assert_eq!(negotiate("sz", &offered, Some(ApiVersion::V10_0)).unwrap(), ApiVersion::V10_0);
+        // Untested versions can still be asked for
+        assert_eq!(negotiate("sz", &offered, Some(ApiVersion::new(12, 0))).unwrap(), ApiVersion::new(12, 0));
+        let err = negotiate("sz", &offered, Some(ApiVersion::new(13, 0))).unwrap_err();
+        assert!(matches!(err, SmartZoneError::ApiVersion { .. }), "{err}");
+    }
+
+    #[test]
+    fn no_common_version() {
+        let err = negotiate("https://sz", &offered(&["v9_0", "v9_1"]), None).unwrap_err();
+        match err {
+            SmartZoneError::ApiVersion { url, supported } => {
+                assert_eq!(url, "https://sz");
+                assert_eq!(supported, ["v9_0", "v9_1"]);
+            }
+            other => panic!("unexpected {other}"),
+        }
+    }
+}
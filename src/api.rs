@@ -0,0 +1,428 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use rocket::{get, http::ContentType, response::status::BadRequest, routes, FromForm, Route, State};
+use serde::Serialize;
+use smartzone::{Client, SmartZoneApi, Zone, AP};
+
+use crate::{
+    collector::{ActiveCollectors, Controller},
+    zones::ZoneFilter,
+};
+
+/// Records per page when `limit` is not given.
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+type Json = (ContentType, String);
+
+pub fn routes() -> Vec<Route> {
+    routes![zones, aps, ap, clients, client]
+}
+
+/// A zone and how many devices the last polls found in it.
+#[derive(Serialize, Debug)]
+pub struct ZoneView {
+    pub controller: String,
+    pub id: String,
+    pub name: String,
+    pub aps: usize,
+    pub clients: usize,
+}
+
+/// The fields of an AP served on `/api/aps`. These names are part of the
+/// api and do not follow the controller's.
+#[derive(Serialize, Debug)]
+pub struct ApView {
+    pub mac: String,
+    pub name: String,
+    pub controller: String,
+    pub zone: String,
+    pub zone_id: String,
+    pub ap_group: String,
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+    pub ip: String,
+    pub status: String,
+    pub description: String,
+    pub location: String,
+    /// Unix time in seconds.
+    pub last_seen: u64,
+    pub uptime_seconds: u64,
+    pub clients: u64,
+    pub alerts: u64,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+}
+
+impl ApView {
+    fn new<A>(controller: &Controller<A>, zone: &Zone, ap: &AP) -> Self {
+        Self {
+            mac: ap.ap_mac.clone(),
+            name: ap.device_name.clone(),
+            controller: controller.name.clone(),
+            zone: zone.name.clone(),
+            zone_id: zone.id.clone(),
+            ap_group: ap.ap_group_name.clone(),
+            model: ap.model.clone(),
+            serial: ap.serial.clone(),
+            firmware: ap.firmware_version.clone(),
+            ip: ap.ip.clone(),
+            status: ap.status.clone(),
+            description: ap.description.clone(),
+            location: ap.location.clone(),
+            last_seen: ap.last_seen / 1000,
+            uptime_seconds: ap.uptime,
+            clients: ap.num_clients,
+            alerts: ap.alerts,
+            tx_bytes: ap.tx,
+            rx_bytes: ap.rx,
+        }
+    }
+}
+
+/// The fields of a client served on `/api/clients`. The MAC, hostname, IP
+/// and username go through the controller's privacy policy like the
+/// metric labels do, and are `null` when it drops them.
+#[derive(Serialize, Debug)]
+pub struct ClientView {
+    pub mac: Option<String>,
+    pub hostname: Option<String>,
+    pub ip: Option<String>,
+    pub user_name: Option<String>,
+    pub controller: String,
+    pub zone: String,
+    pub zone_id: String,
+    pub ap_mac: String,
+    pub ap_name: String,
+    pub ssid: String,
+    pub vlan: u64,
+    pub os_type: String,
+    pub os_vendor: String,
+    pub model: String,
+    pub radio_type: String,
+    pub channel: u64,
+    pub rssi: i64,
+    pub snr: i64,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    pub uplink_rate: u64,
+    pub downlink_rate: u64,
+    pub session_start_time: u64,
+}
+
+impl ClientView {
+    fn new<A>(controller: &Controller<A>, zone: &Zone, client: &Client) -> Self {
+        let privacy = &controller.privacy;
+        Self {
+            mac: privacy.apply(privacy.mac, &client.client_mac),
+            hostname: privacy.apply(privacy.hostname, &client.hostname),
+            ip: privacy.apply(privacy.ip, &client.ip_address),
+            user_name: privacy.apply(privacy.user_name, &client.user_name),
+            controller: controller.name.clone(),
+            zone: zone.name.clone(),
+            zone_id: zone.id.clone(),
+            ap_mac: client.ap_mac.clone(),
+            ap_name: client.ap_name.clone(),
+            ssid: client.ssid.clone(),
+            vlan: client.vlan,
+            os_type: client.os_type.clone(),
+            os_vendor: client.os_vendor_type.clone(),
+            model: client.model_name.clone(),
+            radio_type: client.radio_type.clone(),
+            channel: client.channel,
+            rssi: client.rssi,
+            snr: client.snr,
+            tx_bytes: client.tx_bytes,
+            rx_bytes: client.rx_bytes,
+            uplink_rate: client.uplink_rate,
+            downlink_rate: client.downlink_rate,
+            session_start_time: client.session_start_time,
+        }
+    }
+}
+
+/// One page of results, shaped like the controller's own query responses.
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub total_count: usize,
+    pub page: usize,
+    pub limit: usize,
+    pub has_more: bool,
+    pub list: Vec<T>,
+}
+
+impl<T> Page<T> {
+    /// `page` counts from 1.
+    fn of(all: Vec<T>, page: Option<usize>, limit: Option<usize>) -> Result<Self, BadRequest<String>> {
+        let page = page.unwrap_or(1);
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        if page == 0 {
+            return Err(BadRequest("page counts from 1".to_string()));
+        }
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(BadRequest(format!("limit must be between 1 and {MAX_LIMIT}")));
+        }
+        let total_count = all.len();
+        let start = (page - 1).saturating_mul(limit);
+        let list: Vec<T> = all.into_iter().skip(start).take(limit).collect();
+        Ok(Self {
+            total_count,
+            page,
+            limit,
+            has_more: start.saturating_add(list.len()) < total_count,
+            list,
+        })
+    }
+}
+
+#[derive(FromForm, Default)]
+pub struct ApQuery {
+    /// Picks zones like `?zone=` on `/metrics`, can be repeated.
+    zone: Vec<String>,
+    controller: Option<String>,
+    page: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[derive(FromForm, Default)]
+pub struct ClientQuery {
+    zone: Vec<String>,
+    controller: Option<String>,
+    ssid: Option<String>,
+    /// The MAC or name of the AP the client is on.
+    ap: Option<String>,
+    os_type: Option<String>,
+    page: Option<usize>,
+    limit: Option<usize>,
+}
+
+impl ClientQuery {
+    fn matches(&self, client: &ClientView) -> bool {
+        let is = |wanted: &Option<String>, value: &str| wanted.as_ref().is_none_or(|w| w.eq_ignore_ascii_case(value));
+        is(&self.ssid, &client.ssid)
+            && is(&self.os_type, &client.os_type)
+            && self
+                .ap
+                .as_ref()
+                .is_none_or(|ap| ap.eq_ignore_ascii_case(&client.ap_mac) || ap.eq_ignore_ascii_case(&client.ap_name))
+    }
+}
+
+fn is_controller<A>(wanted: &Option<String>, controller: &Controller<A>) -> bool {
+    wanted.as_ref().is_none_or(|name| name == &controller.name)
+}
+
+fn json(value: &impl Serialize) -> Json {
+    (ContentType::JSON, serde_json::to_string_pretty(value).unwrap())
+}
+
+fn zone_view<'a, A>(
+    zones: &'a mut BTreeMap<(String, String, String), ZoneView>,
+    controller: &Controller<A>,
+    zone: &Zone,
+) -> &'a mut ZoneView {
+    zones
+        .entry((controller.name.clone(), zone.name.clone(), zone.id.clone()))
+        .or_insert_with(|| ZoneView {
+            controller: controller.name.clone(),
+            id: zone.id.clone(),
+            name: zone.name.clone(),
+            aps: 0,
+            clients: 0,
+        })
+}
+
+// Every zone the collectors polled, with how many APs and clients are in it.
+#[get("/api/zones")]
+async fn zones(collectors: &State<Arc<ActiveCollectors>>) -> Json {
+    let collectors = collectors.get();
+    let mut zones = BTreeMap::new();
+    for c in &collectors.aps {
+        for zone in c.zones() {
+            zone_view(&mut zones, &c.controller, &zone);
+        }
+        for zone in c.devices(|zone, _| Some(zone.clone())) {
+            zone_view(&mut zones, &c.controller, &zone).aps += 1;
+        }
+    }
+    for c in &collectors.clients {
+        for zone in c.zones() {
+            zone_view(&mut zones, &c.controller, &zone);
+        }
+        for zone in c.devices(|zone, _| Some(zone.clone())) {
+            zone_view(&mut zones, &c.controller, &zone).clients += 1;
+        }
+    }
+    json(&zones.into_values().collect::<Vec<_>>())
+}
+
+fn find_aps<A: SmartZoneApi + 'static>(collectors: &ActiveCollectors<A>, query: &ApQuery) -> Result<Vec<ApView>, BadRequest<String>> {
+    let filter = ZoneFilter::parse(&query.zone, &[]).map_err(BadRequest)?;
+    let mut aps: Vec<ApView> = collectors
+        .get()
+        .aps
+        .iter()
+        .filter(|c| is_controller(&query.controller, &c.controller))
+        .flat_map(|c| {
+            c.devices(|zone, ap| filter.allows(zone).then(|| ApView::new(&c.controller, zone, ap)))
+        })
+        .collect();
+    aps.sort_by(|a, b| (&a.controller, &a.zone, &a.mac).cmp(&(&b.controller, &b.zone, &b.mac)));
+    Ok(aps)
+}
+
+fn find_clients<A: SmartZoneApi + 'static>(collectors: &ActiveCollectors<A>, query: &ClientQuery) -> Result<Vec<ClientView>, BadRequest<String>> {
+    let filter = ZoneFilter::parse(&query.zone, &[]).map_err(BadRequest)?;
+    let mut clients: Vec<ClientView> = collectors
+        .get()
+        .clients
+        .iter()
+        .filter(|c| is_controller(&query.controller, &c.controller))
+        .flat_map(|c| {
+            c.devices(|zone, client| {
+                filter
+                    .allows(zone)
+                    .then(|| ClientView::new(&c.controller, zone, client))
+                    .filter(|view| query.matches(view))
+            })
+        })
+        .collect();
+    clients.sort_by(|a, b| (&a.controller, &a.zone, &a.ap_mac, &a.mac).cmp(&(&b.controller, &b.zone, &b.ap_mac, &b.mac)));
+    Ok(clients)
+}
+
+#[get("/api/aps?<query..>")]
+async fn aps(collectors: &State<Arc<ActiveCollectors>>, query: ApQuery) -> Result<Json, BadRequest<String>> {
+    let aps = find_aps(collectors, &query)?;
+    Ok(json(&Page::of(aps, query.page, query.limit)?))
+}
+
+// MACs are matched without regard to case.
+#[get("/api/aps/<mac>")]
+async fn ap(collectors: &State<Arc<ActiveCollectors>>, mac: &str) -> Option<Json> {
+    let aps = find_aps(collectors, &ApQuery::default()).ok()?;
+    aps.into_iter().find(|ap| ap.mac.eq_ignore_ascii_case(mac)).map(|ap| json(&ap))
+}
+
+#[get("/api/clients?<query..>")]
+async fn clients(collectors: &State<Arc<ActiveCollectors>>, query: ClientQuery) -> Result<Json, BadRequest<String>> {
+    let clients = find_clients(collectors, &query)?;
+    Ok(json(&Page::of(clients, query.page, query.limit)?))
+}
+
+// Looks the client up by the MAC it is exported with, so with a hashing
+// privacy policy this takes the hash, and with one that drops MACs nothing
+// is found.
+#[get("/api/clients/<mac>")]
+async fn client(collectors: &State<Arc<ActiveCollectors>>, mac: &str) -> Option<Json> {
+    find_client(collectors, mac).map(|client| json(&client))
+}
+
+fn find_client<A: SmartZoneApi + 'static>(collectors: &ActiveCollectors<A>, mac: &str) -> Option<ClientView> {
+    let clients = find_clients(collectors, &ClientQuery::default()).ok()?;
+    clients
+        .into_iter()
+        .find(|client| client.mac.as_ref().is_some_and(|m| m.eq_ignore_ascii_case(mac)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use opentelemetry::global;
+
+    use super::*;
+    use crate::{
+        collector::{Collector, Collectors},
+        fake::FakeSmartZone,
+        privacy::PrivacyPolicy,
+    };
+
+    fn client(mac: &str, ap_mac: &str, ap_name: &str, ssid: &str, os_type: &str) -> Client {
+        Client {
+            client_mac: mac.to_string(),
+            ap_mac: ap_mac.to_string(),
+            ap_name: ap_name.to_string(),
+            ssid: ssid.to_string(),
+            os_type: os_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Collectors that polled a school with a library zone once.
+    async fn polled(privacy: PrivacyPolicy) -> ActiveCollectors<FakeSmartZone> {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let library = FakeSmartZone::zone("2", "Library");
+        let fake = FakeSmartZone::new()
+            .with_clients(&main, vec![
+                client("cc:01", "AA:01", "AP-Hall-1", "Staff", "Windows"),
+                client("cc:02", "AA:01", "AP-Hall-1", "Students", "Chrome OS"),
+            ])
+            .with_clients(&library, vec![client("cc:03", "AA:04", "AP-Library-1", "Staff", "Chrome OS")]);
+        let controller = Arc::new(Controller {
+            name: "school".to_string(),
+            api: fake,
+            privacy: Arc::new(privacy),
+            zones: ZoneFilter::default(),
+            disabled_metrics: Arc::new([]),
+        });
+        let clients = Collector::<Client, _>::new(controller, Duration::from_secs(60), Duration::ZERO);
+        assert!(clients.poll(&global::meter("test")).await);
+        let active = ActiveCollectors::default();
+        active.replace(Collectors { aps: Vec::new(), clients: vec![clients] });
+        active
+    }
+
+    fn macs(collectors: &ActiveCollectors<FakeSmartZone>, query: ClientQuery) -> Vec<String> {
+        let clients = find_clients(collectors, &query).unwrap();
+        clients.into_iter().filter_map(|c| c.mac).collect()
+    }
+
+    #[tokio::test]
+    async fn filters_clients() {
+        let collectors = polled(PrivacyPolicy::default()).await;
+        let all = ClientQuery::default;
+        assert_eq!(macs(&collectors, all()), ["cc:03", "cc:01", "cc:02"]);
+        assert_eq!(macs(&collectors, ClientQuery { zone: vec!["Main Campus".to_string()], ..all() }), ["cc:01", "cc:02"]);
+        assert_eq!(macs(&collectors, ClientQuery { zone: vec!["id:2".to_string()], ..all() }), ["cc:03"]);
+        assert_eq!(macs(&collectors, ClientQuery { ssid: Some("staff".to_string()), ..all() }), ["cc:03", "cc:01"]);
+        assert_eq!(macs(&collectors, ClientQuery { os_type: Some("chrome os".to_string()), ..all() }), ["cc:03", "cc:02"]);
+        assert_eq!(macs(&collectors, ClientQuery { ap: Some("aa:04".to_string()), ..all() }), ["cc:03"]);
+        assert_eq!(macs(&collectors, ClientQuery { ap: Some("ap-hall-1".to_string()), ..all() }), ["cc:01", "cc:02"]);
+        let staff_in_main = ClientQuery {
+            zone: vec!["Main Campus".to_string()],
+            ssid: Some("Staff".to_string()),
+            ..all()
+        };
+        assert_eq!(macs(&collectors, staff_in_main), ["cc:01"]);
+        assert!(macs(&collectors, ClientQuery { controller: Some("admin".to_string()), ..all() }).is_empty());
+    }
+
+    #[tokio::test]
+    async fn looks_clients_up_by_hashed_mac() {
+        let privacy = PrivacyPolicy::parse("mac=hash", Some(b"secret".to_vec())).unwrap();
+        let hashed = privacy.apply(privacy.mac, "cc:03").unwrap();
+        let collectors = polled(privacy).await;
+
+        assert!(find_client(&collectors, "cc:03").is_none());
+        let client = find_client(&collectors, &hashed.to_uppercase()).unwrap();
+        assert_eq!(client.mac, Some(hashed));
+        assert_eq!(client.ap_name, "AP-Library-1");
+    }
+
+    #[test]
+    fn pages_count_from_one() {
+        let page = Page::of((1..=5).collect(), Some(2), Some(2)).unwrap();
+        assert_eq!(page.list, [3, 4]);
+        assert_eq!(page.total_count, 5);
+        assert!(page.has_more);
+
+        let last = Page::of((1..=5).collect(), Some(3), Some(2)).unwrap();
+        assert_eq!(last.list, [5]);
+        assert!(!last.has_more);
+
+        assert!(Page::of(Vec::<i32>::new(), Some(0), None).is_err());
+        assert!(Page::of(Vec::<i32>::new(), None, Some(MAX_LIMIT + 1)).is_err());
+    }
+}
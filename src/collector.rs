@@ -0,0 +1,578 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use opentelemetry::{
+    metrics::{Meter, ObservableGauge},
+    KeyValue,
+};
+use prometheus::proto::MetricFamily;
+use smartzone::{Auth, Client, Query, Record, SmartZoneApi, Zone, AP};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    instruments::{self, CycleMeter},
+    otlp,
+    privacy::PrivacyPolicy,
+    zones::{self, ZoneFilter},
+};
+
+/// A smartzone controller and its session, or anything else that answers
+/// like one.
+pub struct Controller<A = Auth> {
+    pub name: String,
+    pub api: A,
+    /// Applied to client labels before they are exported.
+    pub privacy: Arc<PrivacyPolicy>,
+    pub zones: ZoneFilter,
+    /// AP and client metrics that are not recorded.
+    pub disabled_metrics: Arc<[String]>,
+}
+
+/// A kind of record that is polled in the background and turned into metrics.
+pub trait Collect: Record + Send + Sync + 'static {
+    /// Used as the `Collector` label.
+    const NAME: &'static str;
+    const QUERY: Query;
+    /// The json fields asked for, everything if empty. Whatever is exported
+    /// on `/metrics`, `/clients` or `/api` has to be in here.
+    const ATTRIBUTES: &'static [&'static str] = &[];
+
+    /// Identifies the device across polls.
+    fn key(&self) -> &str;
+    /// Which of two records with the same key to export when the controller
+    /// returns a device more than once. The first one is kept by default.
+    fn preferred_over(&self, _other: &Self) -> bool {
+        false
+    }
+    fn record<A>(&self, meter: &Meter, controller: &Controller<A>, zone: &Zone);
+}
+
+impl Collect for AP {
+    const NAME: &'static str = "aps";
+    const QUERY: Query = Query::Aps;
+
+    fn key(&self) -> &str {
+        &self.ap_mac
+    }
+
+    /// An online AP wins over one that is not, then the one the controller
+    /// heard from last.
+    fn preferred_over(&self, other: &Self) -> bool {
+        let online = |ap: &AP| ap.status == "Online";
+        (online(self), self.last_seen) > (online(other), other.last_seen)
+    }
+
+    fn record<A>(&self, meter: &Meter, controller: &Controller<A>, zone: &Zone) {
+        instruments::record_ap(meter, controller, zone, self);
+    }
+}
+
+impl Collect for Client {
+    const NAME: &'static str = "clients";
+    const QUERY: Query = Query::Clients;
+    // Client records are large and there are many of them, the AP radios are
+    // spread over too many fields to be worth listing.
+    const ATTRIBUTES: &'static [&'static str] = &[
+        "apMac", "apName", "channel", "clientMac", "downlinkRate", "hostname", "ipAddress", "modelName", "osType",
+        "osVendorType", "radioType", "rssi", "rxBytes", "rxFrames", "sessionStartTime", "snr", "ssid", "txBytes",
+        "txDropDataFrames", "txFrames", "uplinkRate", "userName", "vlan",
+    ];
+
+    fn key(&self) -> &str {
+        &self.client_mac
+    }
+
+    fn record<A>(&self, meter: &Meter, controller: &Controller<A>, zone: &Zone) {
+        instruments::record_client(meter, controller, zone, self);
+    }
+}
+
+/// The metrics recorded by the last completed poll.
+#[derive(Default)]
+pub struct Snapshot {
+    pub families: Vec<MetricFamily>,
+    /// The zones that were polled, plus those of devices kept for `grace`.
+    pub zones: Vec<Zone>,
+    pub updated: Option<SystemTime>,
+}
+
+/// The last values of a device and when it was last returned by the controller.
+struct Seen<T> {
+    at: SystemTime,
+    zone: Zone,
+    item: T,
+}
+
+/// Polls one kind of record from one controller on its own interval, so a
+/// slow controller or a large client list does not hold up anything else.
+///
+/// Every poll records into a fresh registry that replaces the previous
+/// snapshot, so devices the controller stops returning disappear from the
+/// output once they have been gone for longer than `grace`.
+pub struct Collector<T, A = Auth> {
+    pub controller: Arc<Controller<A>>,
+    pub interval: Duration,
+    pub grace: Duration,
+    snapshot: RwLock<Snapshot>,
+    seen: Mutex<HashMap<String, Seen<T>>>,
+    /// Whether the last query of each zone succeeded, by zone name.
+    zone_success: Mutex<Vec<(String, bool)>>,
+}
+
+impl<T: Collect, A: SmartZoneApi + 'static> Collector<T, A> {
+    pub fn new(controller: Arc<Controller<A>>, interval: Duration, grace: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            controller,
+            interval,
+            grace,
+            snapshot: RwLock::new(Snapshot::default()),
+            seen: Mutex::new(HashMap::new()),
+            zone_success: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn spawn(self: &Arc<Self>, meter: Meter) -> JoinHandle<()> {
+        let collector = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(collector.interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                collector.collect(&meter).await;
+            }
+        })
+    }
+
+    /// The series of the zones that `filter` allows.
+    pub fn families_in(&self, filter: &ZoneFilter) -> Vec<MetricFamily> {
+        let snapshot = self.snapshot.read().unwrap();
+        let names = snapshot
+            .zones
+            .iter()
+            .filter(|zone| filter.allows(zone))
+            .map(|zone| zone.name.as_str())
+            .collect::<HashSet<_>>();
+        zones::select(snapshot.families.clone(), &names)
+    }
+
+    pub fn zones(&self) -> Vec<Zone> {
+        self.snapshot.read().unwrap().zones.clone()
+    }
+
+    /// Maps the devices behind the snapshot, leaving out those `f` returns
+    /// nothing for.
+    pub fn devices<R>(&self, mut f: impl FnMut(&Zone, &T) -> Option<R>) -> Vec<R> {
+        self.seen.lock().unwrap().values().filter_map(|s| f(&s.zone, &s.item)).collect()
+    }
+
+    /// Polls every zone of the controller. A zone that fails does not stop
+    /// the others and keeps its devices from the last poll, and a controller
+    /// that can not list its zones keeps the previous snapshot.
+    async fn collect(&self, meter: &Meter) {
+        let start = Instant::now();
+        let success = self.poll(meter).await;
+        let now = SystemTime::now();
+        instruments::record_collection(meter, &self.labels(), start.elapsed().as_secs_f64(), success.then_some(now));
+    }
+
+    /// Polls the controller once and returns whether every zone was polled.
+    pub(crate) async fn poll(&self, meter: &Meter) -> bool {
+        let controller = &self.controller;
+        let api = &controller.api;
+        let zones = match api.zones().await {
+            Ok(zones) => zones,
+            Err(err) => {
+                eprintln!("Failed to get zones from {}: {err}", controller.name);
+                return false;
+            }
+        };
+
+        let mut polled = Vec::new();
+        let mut failed = HashSet::new();
+        let mut zone_success = Vec::new();
+        for zone in zones.into_iter().filter(|zone| controller.zones.allows(zone)) {
+            match api.query_zone::<T>(&zone, T::QUERY, T::ATTRIBUTES).await {
+                Ok(items) => {
+                    zone_success.push((zone.name.clone(), true));
+                    polled.push((zone, items));
+                }
+                Err(err) => {
+                    eprintln!("Failed to get {} for zone {} on {}: {err}", T::NAME, zone.name, controller.name);
+                    zone_success.push((zone.name.clone(), false));
+                    failed.insert(zone.id);
+                }
+            }
+        }
+        *self.zone_success.lock().unwrap() = zone_success;
+        let discarded = self.update(polled, &failed, SystemTime::now());
+        instruments::record_duplicates(meter, &self.labels(), discarded);
+        failed.is_empty()
+    }
+
+    /// Replaces the snapshot with the polled devices plus any device that
+    /// went missing less than `grace` ago. Devices in the `failed` zones are
+    /// kept as they were, the zone did not say they are gone. Returns how
+    /// many records were dropped as duplicates of another one.
+    fn update(&self, polled: Vec<(Zone, Vec<T>)>, failed: &HashSet<String>, now: SystemTime) -> u64 {
+        let mut current: HashMap<String, (Zone, T)> = HashMap::new();
+        let mut zones = HashMap::new();
+        let mut discarded = 0;
+        for (zone, items) in polled {
+            zones.insert(zone.id.clone(), zone.clone());
+            for item in items {
+                match current.get_mut(item.key()) {
+                    Some(kept) => {
+                        discarded += 1;
+                        if item.preferred_over(&kept.1) {
+                            *kept = (zone.clone(), item);
+                        }
+                    }
+                    None => {
+                        current.insert(item.key().to_string(), (zone.clone(), item));
+                    }
+                }
+            }
+        }
+        // Only the count, the keys of clients are MACs the privacy policy
+        // may not allow to show
+        if discarded > 0 {
+            eprintln!("{} returned {discarded} {} more than once, kept one of each", self.controller.name, T::NAME);
+        }
+
+        let mut seen = self.seen.lock().unwrap();
+        for s in seen.values_mut().filter(|s| failed.contains(&s.zone.id)) {
+            s.at = now;
+        }
+        for (key, (zone, item)) in current {
+            seen.insert(key, Seen { at: now, zone, item });
+        }
+        seen.retain(|_, s| now.duration_since(s.at).unwrap_or_default() <= self.grace);
+
+        let resource = otlp::resource([
+            KeyValue::new("smartzone.controller.name", self.controller.name.clone()),
+            KeyValue::new("smartzone.controller.url", self.controller.api.url().to_string()),
+        ]);
+        let cycle = CycleMeter::new(resource, &self.controller.disabled_metrics);
+        for s in seen.values() {
+            s.item.record(&cycle.meter, &self.controller, &s.zone);
+            zones.entry(s.zone.id.clone()).or_insert_with(|| s.zone.clone());
+        }
+        *self.snapshot.write().unwrap() = Snapshot {
+            families: cycle.finish(),
+            zones: zones.into_values().collect(),
+            updated: Some(now),
+        };
+        discarded
+    }
+}
+
+/// Every collector, shared with the http handlers.
+pub struct Collectors<A = Auth> {
+    pub aps: Vec<Arc<Collector<AP, A>>>,
+    pub clients: Vec<Arc<Collector<Client, A>>>,
+}
+
+// Derived it would need `A: Default`
+impl<A> Default for Collectors<A> {
+    fn default() -> Self {
+        Self { aps: Vec::new(), clients: Vec::new() }
+    }
+}
+
+impl<A: SmartZoneApi + 'static> Collectors<A> {
+    pub fn statuses(&self) -> Vec<Arc<dyn CollectorStatus>> {
+        let aps = self.aps.iter().map(|c| c.clone() as Arc<dyn CollectorStatus>);
+        let clients = self.clients.iter().map(|c| c.clone() as Arc<dyn CollectorStatus>);
+        aps.chain(clients).collect()
+    }
+}
+
+/// The collectors of the config in use, swapped out when it is reloaded.
+pub struct ActiveCollectors<A = Auth>(RwLock<Arc<Collectors<A>>>);
+
+impl<A> Default for ActiveCollectors<A> {
+    fn default() -> Self {
+        Self(RwLock::default())
+    }
+}
+
+impl<A> ActiveCollectors<A> {
+    pub fn get(&self) -> Arc<Collectors<A>> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Returns the collectors that were replaced.
+    pub fn replace(&self, collectors: Collectors<A>) -> Arc<Collectors<A>> {
+        std::mem::replace(&mut *self.0.write().unwrap(), Arc::new(collectors))
+    }
+}
+
+/// Type erased view of a collector for reporting on it.
+pub trait CollectorStatus: Send + Sync {
+    fn labels(&self) -> [KeyValue; 2];
+    fn updated(&self) -> Option<SystemTime>;
+    fn zone_success(&self) -> Vec<(String, bool)>;
+}
+
+impl<T: Collect, A: SmartZoneApi> CollectorStatus for Collector<T, A> {
+    fn labels(&self) -> [KeyValue; 2] {
+        [
+            KeyValue::new("Controller", self.controller.name.clone()),
+            KeyValue::new("Collector", T::NAME),
+        ]
+    }
+
+    fn updated(&self) -> Option<SystemTime> {
+        self.snapshot.read().unwrap().updated
+    }
+
+    fn zone_success(&self) -> Vec<(String, bool)> {
+        self.zone_success.lock().unwrap().clone()
+    }
+}
+
+/// Reports how old each collector's snapshot is whenever metrics are gathered.
+/// Collectors that have not finished a poll yet are left out.
+pub fn observe_snapshot_age(meter: &Meter, collectors: Arc<ActiveCollectors>) -> ObservableGauge<f64> {
+    meter
+        .f64_observable_gauge("smartzone_exporter_snapshot_age_seconds")
+        .with_description("Seconds since the collector last finished polling the controller")
+        .with_callback(move |observer| {
+            for collector in &collectors.get().statuses() {
+                if let Some(age) = collector.updated().and_then(|t| t.elapsed().ok()) {
+                    observer.observe(age.as_secs_f64(), &collector.labels());
+                }
+            }
+        })
+        .init()
+}
+
+/// Reports whether each zone's last query succeeded whenever metrics are
+/// gathered. Only the active collectors are asked, so zones and controllers
+/// that were removed by a reload drop out.
+pub fn observe_zone_success<A: SmartZoneApi + 'static>(
+    meter: &Meter,
+    collectors: Arc<ActiveCollectors<A>>,
+) -> ObservableGauge<u64> {
+    meter
+        .u64_observable_gauge("smartzone_zone_scrape_success")
+        .with_description("1 if the last scrape of the zone succeeded, 0 if it failed")
+        .with_callback(move |observer| {
+            for collector in &collectors.get().statuses() {
+                let [controller, name] = collector.labels();
+                for (zone, success) in collector.zone_success() {
+                    let labels = [KeyValue::new("Zone", zone), controller.clone(), name.clone()];
+                    observer.observe(success as u64, &labels);
+                }
+            }
+        })
+        .init()
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry_sdk::Resource;
+
+    use super::*;
+    use crate::fake::FakeSmartZone;
+
+    fn collector<T: Collect>(grace: Duration) -> Arc<Collector<T, FakeSmartZone>> {
+        collector_on(FakeSmartZone::new(), grace, ZoneFilter::default())
+    }
+
+    fn collector_on<T: Collect>(fake: FakeSmartZone, grace: Duration, zones: ZoneFilter) -> Arc<Collector<T, FakeSmartZone>> {
+        let controller = Arc::new(Controller {
+            name: "test".to_string(),
+            api: fake,
+            privacy: Arc::default(),
+            zones,
+            disabled_metrics: Arc::new([]),
+        });
+        Collector::new(controller, Duration::from_secs(60), grace)
+    }
+
+    fn ap(mac: &str, name: &str, clients: u64) -> AP {
+        AP {
+            ap_mac: mac.to_string(),
+            device_name: name.to_string(),
+            status: "Online".to_string(),
+            num_clients: clients,
+            ..Default::default()
+        }
+    }
+
+    fn meter() -> Meter {
+        opentelemetry::global::meter("test")
+    }
+
+    fn zone() -> Zone {
+        Zone { id: "1".to_string(), name: "School".to_string() }
+    }
+
+    fn client(mac: &str) -> Client {
+        Client { client_mac: mac.to_string(), ..Default::default() }
+    }
+
+    fn output<T: Collect>(collector: &Collector<T, FakeSmartZone>) -> String {
+        instruments::encode(&instruments::merge(collector.families_in(&ZoneFilter::default())))
+    }
+
+    #[test]
+    fn removed_client_disappears() {
+        let collector = collector::<Client>(Duration::ZERO);
+        let start = SystemTime::now();
+
+        collector.update(vec![(zone(), vec![client("aa:aa"), client("bb:bb")])], &HashSet::new(), start);
+        let before = output(&collector);
+        assert!(before.contains(r#"Mac="aa:aa""#));
+        assert!(before.contains(r#"Mac="bb:bb""#));
+
+        collector.update(vec![(zone(), vec![client("aa:aa")])], &HashSet::new(), start + Duration::from_secs(60));
+        let after = output(&collector);
+        assert!(after.contains(r#"Mac="aa:aa""#));
+        assert!(!after.contains(r#"Mac="bb:bb""#));
+    }
+
+    #[test]
+    fn grace_period_keeps_missing_client() {
+        let collector = collector::<Client>(Duration::from_secs(120));
+        let start = SystemTime::now();
+
+        collector.update(vec![(zone(), vec![client("aa:aa")])], &HashSet::new(), start);
+        collector.update(vec![(zone(), Vec::new())], &HashSet::new(), start + Duration::from_secs(60));
+        assert!(output(&collector).contains(r#"Mac="aa:aa""#));
+
+        collector.update(vec![(zone(), Vec::new())], &HashSet::new(), start + Duration::from_secs(180));
+        assert!(!output(&collector).contains(r#"Mac="aa:aa""#));
+    }
+
+    #[test]
+    fn families_in_selects_zones() {
+        let collector = collector::<Client>(Duration::ZERO);
+        let lab = Zone { id: "2".to_string(), name: "Lab".to_string() };
+        let polled = vec![(zone(), vec![client("aa:aa")]), (lab, vec![client("bb:bb")])];
+        collector.update(polled, &HashSet::new(), SystemTime::now());
+
+        let filter = ZoneFilter::parse(&["id:2"], &[]).unwrap();
+        let output = instruments::encode(&instruments::merge(collector.families_in(&filter)));
+        assert!(!output.contains(r#"Mac="aa:aa""#));
+        assert!(output.contains(r#"Mac="bb:bb""#));
+    }
+
+    #[test]
+    fn duplicate_ap_prefers_online_then_newest() {
+        let collector = collector::<AP>(Duration::ZERO);
+        let ap = |serial: &str, status: &str, last_seen| AP {
+            ap_mac: "aa:aa".to_string(),
+            serial: serial.to_string(),
+            status: status.to_string(),
+            last_seen,
+            ..Default::default()
+        };
+
+        let discarded = collector.update(
+            vec![(zone(), vec![ap("old", "Online", 1), ap("offline", "Offline", 3), ap("new", "Online", 2)])],
+            &HashSet::new(),
+            SystemTime::now(),
+        );
+        assert_eq!(discarded, 2);
+        let output = output(&collector);
+        assert!(output.contains(r#"Serial="new""#));
+        assert_eq!(output.matches("ap_info{").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn poll_records_every_zone() {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let library = FakeSmartZone::zone("2", "Library");
+        let fake = FakeSmartZone::new()
+            .with_aps(&main, vec![ap("aa:01", "AP-Hall", 3), ap("aa:02", "AP-Gym", 0)])
+            .with_aps(&library, vec![ap("aa:03", "AP-Library", 7)]);
+        let collector = collector_on::<AP>(fake, Duration::ZERO, ZoneFilter::default());
+
+        assert!(collector.poll(&meter()).await);
+        let output = output(&collector);
+        assert!(output.contains(r#"ap_clients{Controller="test",MAC="aa:01",Zone="Main Campus""#));
+        assert!(output.contains(r#"DeviceName="AP-Gym""#));
+        assert!(output.contains(r#"ap_clients{Controller="test",MAC="aa:03",Zone="Library""#));
+    }
+
+    #[tokio::test]
+    async fn failing_zone_does_not_stop_the_others() {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let library = FakeSmartZone::zone("2", "Library");
+        let working = FakeSmartZone::new()
+            .with_clients(&main, vec![client("cc:01")])
+            .with_clients(&library, vec![client("cc:02")]);
+        let collector = collector_on::<Client>(working, Duration::ZERO, ZoneFilter::default());
+        assert!(collector.poll(&meter()).await);
+
+        collector.controller.api.answer_like(
+            FakeSmartZone::new()
+                .with_failing_zone(&library)
+                .with_clients(&main, vec![client("cc:03")]),
+        );
+        assert!(!collector.poll(&meter()).await);
+        let output = output(&collector);
+        assert!(output.contains(r#"Mac="cc:03""#));
+        assert!(!output.contains(r#"Mac="cc:01""#));
+        // The failed zone keeps what it had, even without a grace period
+        assert!(output.contains(r#"Mac="cc:02""#));
+    }
+
+    #[tokio::test]
+    async fn filtered_zones_are_not_queried() {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let lab = FakeSmartZone::zone("2", "Lab");
+        let fake = FakeSmartZone::new()
+            .with_aps(&main, vec![ap("aa:01", "AP-Hall", 1)])
+            .with_aps(&lab, vec![ap("aa:02", "AP-Lab", 1)]);
+        let collector = collector_on::<AP>(fake, Duration::ZERO, ZoneFilter::parse(&[] as &[&str], &["Lab"]).unwrap());
+
+        assert!(collector.poll(&meter()).await);
+        assert_eq!(collector.controller.api.queried(), ["1"]);
+        assert!(!output(&collector).contains("AP-Lab"));
+    }
+
+    #[tokio::test]
+    async fn zone_success_is_observed_from_the_active_collectors() {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let library = FakeSmartZone::zone("2", "Library");
+        let fake = FakeSmartZone::new()
+            .with_aps(&main, vec![ap("aa:01", "AP-Hall", 1)])
+            .with_failing_zone(&library);
+        let collector = collector_on::<AP>(fake, Duration::ZERO, ZoneFilter::default());
+        assert!(!collector.poll(&meter()).await);
+
+        let active = Arc::new(ActiveCollectors::default());
+        active.replace(Collectors { aps: vec![collector], clients: Vec::new() });
+        let observed = || {
+            let cycle = CycleMeter::new(Resource::empty(), &[]);
+            let _gauge = observe_zone_success(&cycle.meter, active.clone());
+            instruments::encode(&cycle.finish())
+        };
+        let output = observed();
+        let success = r#"smartzone_zone_scrape_success{Collector="aps",Controller="test""#;
+        assert!(output.contains(&format!(r#"{success},Zone="Main Campus"}} 1"#)), "{output}");
+        assert!(output.contains(&format!(r#"{success},Zone="Library"}} 0"#)), "{output}");
+
+        // Reloaded without the controller
+        active.replace(Collectors::default());
+        assert!(!observed().contains("smartzone_zone_scrape_success"));
+    }
+
+    #[tokio::test]
+    async fn failed_zone_list_keeps_snapshot() {
+        let main = FakeSmartZone::zone("1", "Main Campus");
+        let fake = FakeSmartZone::new().with_aps(&main, vec![ap("aa:01", "AP-Hall", 1)]);
+        let collector = collector_on::<AP>(fake, Duration::ZERO, ZoneFilter::default());
+        assert!(collector.poll(&meter()).await);
+
+        collector.controller.api.answer_like(FakeSmartZone::new().without_zones());
+        assert!(!collector.poll(&meter()).await);
+        assert!(output(&collector).contains("AP-Hall"));
+    }
+}
@@ -0,0 +1,497 @@
+use std::{collections::HashSet, fmt, fs, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+use smartzone::{
+    cassette::CassetteMode,
+    tls::{self, TlsConfig},
+    version::ApiVersion,
+    DEFAULT_PAGE_CONCURRENCY, DEFAULT_PAGE_SIZE, DEFAULT_TIME_ZONE,
+};
+
+use crate::{
+    privacy::{FieldPolicy, PrivacyPolicy},
+    zones::ZoneFilter,
+};
+
+pub const DEFAULT_AP_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_CLIENT_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Everything needed to talk to one smartzone controller.
+#[derive(Clone)]
+pub struct ControllerConfig {
+    /// Added to every series as the `Controller` label.
+    pub name: String,
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub tls: TlsConfig,
+    /// Overrides the negotiated api version.
+    pub api_version: Option<ApiVersion>,
+    pub page_size: usize,
+    pub page_concurrency: usize,
+    /// Lets records with missing or `null` fields decode with defaults.
+    pub lenient: bool,
+    /// UTC offset sent on login, e.g. `-07:00`.
+    pub time_zone: String,
+    pub zones: ZoneFilter,
+    /// Records the controller's traffic or replays a recording.
+    pub cassette: Option<CassetteMode>,
+}
+
+// The password must never end up in a log line.
+impl fmt::Debug for ControllerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ControllerConfig")
+            .field("name", &self.name)
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("tls", &self.tls)
+            .field("api_version", &self.api_version)
+            .field("page_size", &self.page_size)
+            .field("page_concurrency", &self.page_concurrency)
+            .field("lenient", &self.lenient)
+            .field("time_zone", &self.time_zone)
+            .field("zones", &self.zones)
+            .field("cassette", &self.cassette)
+            .finish()
+    }
+}
+
+/// The whole configuration, read from `RUST_CONFIG_FILE` if it is set and
+/// from the environment otherwise.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub controllers: Vec<ControllerConfig>,
+    pub ap_interval: Duration,
+    pub client_interval: Duration,
+    pub grace: Duration,
+    pub privacy: PrivacyPolicy,
+    /// AP and client metrics that are not recorded, `*` matches anything.
+    pub disabled_metrics: Vec<String>,
+}
+
+impl Settings {
+    /// Reads the config file again on every call, so this is also what a
+    /// reload uses.
+    pub fn load() -> Result<Self, String> {
+        match dotenvy::var("RUST_CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path),
+            Err(_) => Self::from_env(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self {
+            controllers: controllers_from_env()?,
+            ap_interval: interval_from_env("RUST_AP_INTERVAL", DEFAULT_AP_INTERVAL)?,
+            client_interval: interval_from_env("RUST_CLIENT_INTERVAL", DEFAULT_CLIENT_INTERVAL)?,
+            grace: grace_from_env("RUST_STALE_GRACE")?,
+            privacy: PrivacyPolicy::from_env()?,
+            disabled_metrics: list_from_env("RUST_DISABLED_METRICS"),
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: FileConfig = toml::from_str(text).map_err(|err| err.to_string())?;
+        file.try_into()
+    }
+}
+
+/// Reads the controllers from the environment.
+///
+/// `RUST_CONTROLLERS='schools,admin'` names the controllers, each of which
+/// then reads `RUST_SCHOOLS_URL`, `RUST_SCHOOLS_USERNAME`,
+/// `RUST_SCHOOLS_PASSWORD`, `RUST_SCHOOLS_API_VERSION`,
+/// `RUST_SCHOOLS_PAGE_SIZE`, `RUST_SCHOOLS_PAGE_CONCURRENCY`,
+/// `RUST_SCHOOLS_LENIENT_DECODE`, `RUST_SCHOOLS_TIME_ZONE`,
+/// `RUST_SCHOOLS_ZONES_INCLUDE`, `RUST_SCHOOLS_ZONES_EXCLUDE`,
+/// `RUST_SCHOOLS_RECORD`, `RUST_SCHOOLS_REPLAY` and `RUST_SCHOOLS_TLS_*`. Without it a single controller called `default` is
+/// read from `RUST_URL`, `RUST_USERNAME`, `RUST_PASSWORD` and so on.
+pub fn controllers_from_env() -> Result<Vec<ControllerConfig>, String> {
+    match dotenvy::var("RUST_CONTROLLERS") {
+        Ok(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| controller_from_env(name, &format!("RUST_{}_", env_name(name))))
+            .collect(),
+        Err(_) => Ok(vec![controller_from_env("default", "RUST_")?]),
+    }
+}
+
+/// Reads the `{prefix}TLS_*` env vars, e.g. `RUST_TLS_CA_FILE`.
+fn tls_from_env(prefix: &str) -> Result<TlsConfig, String> {
+    let var = |name: &str| dotenvy::var(format!("{prefix}TLS_{name}")).ok().filter(|v| !v.is_empty());
+    let config = TlsConfig {
+        ca_file: var("CA_FILE").map(PathBuf::from),
+        pin_sha256: var("PIN_SHA256")
+            .map(|v| tls::parse_fingerprint(&v))
+            .transpose()
+            .map_err(|err| err.to_string())?,
+        client_cert: var("CLIENT_CERT").map(PathBuf::from),
+        client_key: var("CLIENT_KEY").map(PathBuf::from),
+        insecure: var("INSECURE").is_some_and(|v| v == "true" || v == "1"),
+    };
+    config.validate().map_err(|err| err.to_string())?;
+    Ok(config)
+}
+
+fn controller_from_env(name: &str, prefix: &str) -> Result<ControllerConfig, String> {
+    let var = |key: &str| {
+        let key = format!("{prefix}{key}");
+        dotenvy::var(&key).map_err(|_| format!("Set {key}"))
+    };
+    Ok(ControllerConfig {
+        name: name.to_string(),
+        url: var("URL")?,
+        username: var("USERNAME")?,
+        password: var("PASSWORD")?,
+        tls: tls_from_env(prefix).map_err(|err| format!("controller {name}: {err}"))?,
+        api_version: var("API_VERSION")
+            .ok()
+            .map(|v| v.parse())
+            .transpose()
+            .map_err(|err| format!("controller {name}: {err}"))?,
+        page_size: count_from_env(&format!("{prefix}PAGE_SIZE"), DEFAULT_PAGE_SIZE)?,
+        page_concurrency: count_from_env(&format!("{prefix}PAGE_CONCURRENCY"), DEFAULT_PAGE_CONCURRENCY)?,
+        lenient: var("LENIENT_DECODE").is_ok_and(|v| v == "true" || v == "1"),
+        time_zone: var("TIME_ZONE").unwrap_or_else(|_| DEFAULT_TIME_ZONE.to_string()),
+        zones: ZoneFilter::parse(&list_from_env(&format!("{prefix}ZONES_INCLUDE")), &list_from_env(&format!("{prefix}ZONES_EXCLUDE")))
+            .map_err(|err| format!("controller {name}: {err}"))?,
+        cassette: cassette(var("RECORD").ok().map(PathBuf::from), var("REPLAY").ok().map(PathBuf::from))
+            .map_err(|err| format!("controller {name}: {err}"))?,
+    })
+}
+
+fn cassette(record: Option<PathBuf>, replay: Option<PathBuf>) -> Result<Option<CassetteMode>, String> {
+    match (record, replay) {
+        (Some(_), Some(_)) => Err("set only one of record and replay".to_string()),
+        (Some(dir), None) => Ok(Some(CassetteMode::Record(dir))),
+        (None, Some(dir)) => Ok(Some(CassetteMode::Replay(dir))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Reads a comma separated list, empty if the var is not set.
+fn list_from_env(key: &str) -> Vec<String> {
+    dotenvy::var(key)
+        .map(|list| list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn count_from_env(key: &str, default: usize) -> Result<usize, String> {
+    match dotenvy::var(key) {
+        Ok(count) => match count.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("{key} must be a positive number")),
+            Ok(count) => Ok(count),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+/// `north-campus` -> `NORTH_CAMPUS`
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Reads how long a device that disappeared is still reported, in seconds.
+/// Zero, the default, drops it on the next poll.
+pub fn grace_from_env(key: &str) -> Result<Duration, String> {
+    match dotenvy::var(key) {
+        Ok(secs) => secs
+            .parse()
+            .map(Duration::from_secs)
+            .map_err(|_| format!("{key} must be a number of seconds")),
+        Err(_) => Ok(Duration::ZERO),
+    }
+}
+
+/// Reads a poll interval in seconds, e.g. `RUST_AP_INTERVAL='60'`.
+pub fn interval_from_env(key: &str, default: Duration) -> Result<Duration, String> {
+    count_from_env(key, default.as_secs() as usize).map(|secs| Duration::from_secs(secs as u64))
+}
+
+/// A value that is written in the file, or read from an env var or a file
+/// so credentials do not have to sit in the config:
+/// `password = "..."`, `password = { env = "SZ_PASSWORD" }` or
+/// `password = { file = "/run/secrets/sz_password" }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Secret {
+    Plain(String),
+    Env { env: String },
+    File { file: PathBuf },
+}
+
+impl Secret {
+    fn resolve(&self, what: &str) -> Result<String, String> {
+        match self {
+            Self::Plain(value) => Ok(value.clone()),
+            Self::Env { env } => std::env::var(env).map_err(|_| format!("{what}: env var {env} is not set")),
+            Self::File { file } => fs::read_to_string(file)
+                .map(|value| value.trim_end().to_string())
+                .map_err(|err| format!("{what}: failed to read {}: {err}", file.display())),
+        }
+    }
+}
+
+/// The layout of the config file, see the README for an example.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    /// Seconds
+    ap_interval: Option<u64>,
+    client_interval: Option<u64>,
+    stale_grace: Option<u64>,
+    controllers: Vec<ControllerFile>,
+    #[serde(default)]
+    metrics: MetricsFile,
+    #[serde(default)]
+    privacy: PrivacyFile,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ControllerFile {
+    name: String,
+    url: String,
+    username: Secret,
+    password: Secret,
+    api_version: Option<String>,
+    page_size: Option<usize>,
+    page_concurrency: Option<usize>,
+    #[serde(default)]
+    lenient_decode: bool,
+    time_zone: Option<String>,
+    #[serde(default)]
+    tls: TlsFile,
+    #[serde(default)]
+    zones: ZonesFile,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TlsFile {
+    ca_file: Option<PathBuf>,
+    pin_sha256: Option<String>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    #[serde(default)]
+    insecure: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ZonesFile {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MetricsFile {
+    #[serde(default)]
+    disabled: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PrivacyFile {
+    mac: Option<FieldPolicy>,
+    hostname: Option<FieldPolicy>,
+    ip: Option<FieldPolicy>,
+    username: Option<FieldPolicy>,
+    key: Option<Secret>,
+}
+
+impl TryFrom<FileConfig> for Settings {
+    type Error = String;
+
+    fn try_from(file: FileConfig) -> Result<Self, Self::Error> {
+        let seconds = |value: Option<u64>, key: &str, default: Duration| match value {
+            Some(0) => Err(format!("{key} must be a positive number of seconds")),
+            Some(secs) => Ok(Duration::from_secs(secs)),
+            None => Ok(default),
+        };
+
+        if file.controllers.is_empty() {
+            return Err("at least one [[controllers]] is needed".to_string());
+        }
+        let mut names = HashSet::new();
+        let mut controllers = Vec::new();
+        for (i, controller) in file.controllers.into_iter().enumerate() {
+            let what = format!("controllers[{i}] ({})", controller.name);
+            if !names.insert(controller.name.clone()) {
+                return Err(format!("{what}: the name is used by another controller"));
+            }
+            controllers.push(controller.into_config().map_err(|err| format!("{what}: {err}"))?);
+        }
+
+        let mut privacy = PrivacyPolicy::with_key(
+            file.privacy
+                .key
+                .map(|key| key.resolve("privacy.key"))
+                .transpose()?
+                .map(String::into_bytes),
+        );
+        let fields = [
+            (&mut privacy.mac, file.privacy.mac),
+            (&mut privacy.hostname, file.privacy.hostname),
+            (&mut privacy.ip, file.privacy.ip),
+            (&mut privacy.user_name, file.privacy.username),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        Ok(Self {
+            controllers,
+            ap_interval: seconds(file.ap_interval, "ap_interval", DEFAULT_AP_INTERVAL)?,
+            client_interval: seconds(file.client_interval, "client_interval", DEFAULT_CLIENT_INTERVAL)?,
+            grace: Duration::from_secs(file.stale_grace.unwrap_or(0)),
+            privacy: privacy.validate().map_err(|err| format!("privacy: {err}"))?,
+            disabled_metrics: file.metrics.disabled,
+        })
+    }
+}
+
+impl ControllerFile {
+    fn into_config(self) -> Result<ControllerConfig, String> {
+        if self.name.is_empty() {
+            return Err("name can not be empty".to_string());
+        }
+        if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+            return Err(format!("url {} must start with https:// or http://", self.url));
+        }
+        let positive = |value: Option<usize>, key: &str, default: usize| match value {
+            Some(0) => Err(format!("{key} must be a positive number")),
+            Some(n) => Ok(n),
+            None => Ok(default),
+        };
+
+        let tls = TlsConfig {
+            ca_file: self.tls.ca_file,
+            pin_sha256: self
+                .tls
+                .pin_sha256
+                .as_deref()
+                .map(tls::parse_fingerprint)
+                .transpose()
+                .map_err(|err| err.to_string())?,
+            client_cert: self.tls.client_cert,
+            client_key: self.tls.client_key,
+            insecure: self.tls.insecure,
+        };
+        tls.validate().map_err(|err| err.to_string())?;
+
+        Ok(ControllerConfig {
+            username: self.username.resolve("username")?,
+            password: self.password.resolve("password")?,
+            api_version: self.api_version.map(|v| v.parse()).transpose()?,
+            page_size: positive(self.page_size, "page_size", DEFAULT_PAGE_SIZE)?,
+            page_concurrency: positive(self.page_concurrency, "page_concurrency", DEFAULT_PAGE_CONCURRENCY)?,
+            lenient: self.lenient_decode,
+            time_zone: self.time_zone.unwrap_or_else(|| DEFAULT_TIME_ZONE.to_string()),
+            zones: ZoneFilter::parse(&self.zones.include, &self.zones.exclude).map_err(|err| format!("zones: {err}"))?,
+            cassette: cassette(self.record, self.replay)?,
+            name: self.name,
+            url: self.url,
+            tls,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smartzone::Zone;
+
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+        ap_interval = 30
+
+        [[controllers]]
+        name = "schools"
+        url = "https://sz.example.org:8443"
+        username = "monitor"
+        password = "secret"
+        zones = { exclude = ["Lab"] }
+
+        [[controllers]]
+        name = "admin"
+        url = "https://sz2.example.org:8443"
+        username = "monitor"
+        password = "secret"
+        page_size = 500
+
+        [metrics]
+        disabled = ["client_rx", "ap_client_*"]
+
+        [privacy]
+        mac = "hash"
+        key = "hmac-key"
+    "#;
+
+    #[test]
+    fn parses_example() {
+        let settings = Settings::parse(EXAMPLE).unwrap();
+        assert_eq!(settings.ap_interval, Duration::from_secs(30));
+        assert_eq!(settings.client_interval, DEFAULT_CLIENT_INTERVAL);
+        assert_eq!(settings.controllers.len(), 2);
+        let lab = Zone { id: "z-1".to_string(), name: "Lab".to_string() };
+        assert!(matches!(settings.controllers[0].zones.exclude.as_slice(), [only] if only.matches(&lab)));
+        assert!(!settings.controllers[0].zones.allows(&lab));
+        assert!(settings.controllers[1].zones.allows(&lab));
+        assert_eq!(settings.controllers[1].page_size, 500);
+        assert_eq!(settings.controllers[1].time_zone, DEFAULT_TIME_ZONE);
+        assert_eq!(settings.privacy.mac, FieldPolicy::Hash);
+        assert_eq!(settings.privacy.user_name, FieldPolicy::Drop);
+        assert_eq!(settings.disabled_metrics, ["client_rx", "ap_client_*"]);
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        let debug = format!("{:?}", Settings::parse(EXAMPLE).unwrap());
+        assert!(debug.contains("monitor"), "{debug}");
+        assert!(!debug.contains("secret") && !debug.contains("hmac-key"), "{debug}");
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = Settings::parse(&EXAMPLE.replace("page_size", "pagesize")).unwrap_err();
+        assert!(err.contains("pagesize"), "{err}");
+    }
+
+    #[test]
+    fn rejects_invalid_controllers() {
+        let err = Settings::parse(&EXAMPLE.replace("\"admin\"", "\"schools\"")).unwrap_err();
+        assert!(err.starts_with("controllers[1] (schools)"), "{err}");
+        let err = Settings::parse(&EXAMPLE.replace("https://sz2", "sz2")).unwrap_err();
+        assert!(err.ends_with("url sz2.example.org:8443 must start with https:// or http://"), "{err}");
+        assert!(Settings::parse(&EXAMPLE.replace("page_size = 500", "page_size = 0")).is_err());
+        assert!(Settings::parse("controllers = []").is_err());
+        let err = Settings::parse(&EXAMPLE.replace("[\"Lab\"]", "[\"regex:(\"]")).unwrap_err();
+        assert!(err.starts_with("controllers[0] (schools): zones: invalid zone regex"), "{err}");
+    }
+
+    #[test]
+    fn hashing_needs_key() {
+        let err = Settings::parse(&EXAMPLE.replace("key = \"hmac-key\"", "")).unwrap_err();
+        assert!(err.starts_with("privacy:"), "{err}");
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use smartzone::SmartZoneError;
+
+use crate::privacy::PrivacyPolicy;
+
+/// How many failures are kept, older ones are dropped first.
+pub const DECODE_LOG_CAPACITY: usize = 20;
+/// Payloads longer than this are cut off, a whole page can be megabytes.
+const MAX_PAYLOAD: usize = 64 * 1024;
+
+/// A response that could not be decoded, kept so it can be looked at
+/// without reproducing the scrape.
+#[derive(Serialize, Debug, Clone)]
+pub struct DecodeFailure {
+    /// Unix time in seconds.
+    pub at: u64,
+    pub url: String,
+    pub context: String,
+    /// Where in the response decoding stopped, e.g. `list[3].channel24gValue`.
+    pub path: String,
+    pub error: String,
+    /// The failing record, or the whole response if it failed outside of one,
+    /// with the client privacy policy applied.
+    pub payload: String,
+}
+
+impl DecodeFailure {
+    pub fn new(url: &str, err: &SmartZoneError, payload: &str, privacy: &PrivacyPolicy) -> Self {
+        let (context, path, error) = match err {
+            SmartZoneError::Decode { context, path, source } => (context.clone(), path.clone(), source.to_string()),
+            other => (String::new(), String::new(), other.to_string()),
+        };
+        Self {
+            at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            url: url.to_string(),
+            payload: truncate(&scrub(payload, privacy)),
+            context,
+            path,
+            error,
+        }
+    }
+}
+
+/// Client fields are only found in json, anything else is withheld unless the
+/// policy keeps everything anyway.
+fn scrub(payload: &str, privacy: &PrivacyPolicy) -> String {
+    if privacy.keeps_everything() {
+        return payload.to_string();
+    }
+    match serde_json::from_str(payload) {
+        Ok(mut value) => {
+            privacy.scrub(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes withheld, not json>", payload.len()),
+    }
+}
+
+fn truncate(body: &str) -> String {
+    if body.len() <= MAX_PAYLOAD {
+        return body.to_string();
+    }
+    let mut end = MAX_PAYLOAD;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes cut off)", &body[..end], body.len() - end)
+}
+
+/// The most recent decode failures of every controller, served on
+/// `/debug/decode-errors`.
+#[derive(Debug)]
+pub struct DecodeLog {
+    failures: Mutex<VecDeque<DecodeFailure>>,
+    capacity: usize,
+}
+
+impl Default for DecodeLog {
+    fn default() -> Self {
+        Self::new(DECODE_LOG_CAPACITY)
+    }
+}
+
+impl DecodeLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            failures: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, failure: DecodeFailure) {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() == self.capacity {
+            failures.pop_front();
+        }
+        failures.push_back(failure);
+    }
+
+    /// Newest first.
+    pub fn recent(&self) -> Vec<DecodeFailure> {
+        self.failures.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(payload: &str, privacy: &PrivacyPolicy) -> DecodeFailure {
+        let err = serde_json::from_str::<u64>("\"many\"").unwrap_err();
+        let err = SmartZoneError::Decode { context: "query/client page 1".to_string(), path: "list[2].rssi".to_string(), source: err };
+        DecodeFailure::new("https://sz:8443", &err, payload, privacy)
+    }
+
+    #[test]
+    fn keeps_the_newest_failures() {
+        let log = DecodeLog::new(2);
+        for n in 0..3 {
+            log.push(failure(&n.to_string(), &PrivacyPolicy::default()));
+        }
+        let payloads: Vec<_> = log.recent().into_iter().map(|f| f.payload).collect();
+        assert_eq!(payloads, ["2", "1"]);
+        assert_eq!(log.recent()[0].path, "list[2].rssi");
+        assert_eq!(log.recent()[0].context, "query/client page 1");
+    }
+
+    #[test]
+    fn cuts_off_long_payloads_on_a_char_boundary() {
+        let keep = PrivacyPolicy::parse("username=keep", None).unwrap();
+        let short = "x".repeat(MAX_PAYLOAD);
+        assert_eq!(failure(&short, &keep).payload, short);
+
+        // The cut falls in the middle of the two byte 'é'
+        let long = format!("{}é{}", "x".repeat(MAX_PAYLOAD - 1), "x".repeat(10));
+        let payload = failure(&long, &keep).payload;
+        assert!(payload.starts_with(&"x".repeat(MAX_PAYLOAD - 1)));
+        assert!(payload.ends_with("... (12 bytes cut off)"), "{}", &payload[MAX_PAYLOAD - 10..]);
+    }
+
+    #[test]
+    fn applies_the_privacy_policy_to_payloads() {
+        let privacy = PrivacyPolicy::parse("mac=hash", Some(b"secret".to_vec())).unwrap();
+        let record = r#"{"clientMac":"aa:bb","userName":"student","rssi":"many"}"#;
+        let payload = failure(record, &privacy).payload;
+        assert!(!payload.contains("aa:bb") && !payload.contains("student"), "{payload}");
+        assert!(payload.contains(r#""userName":"<dropped>""#), "{payload}");
+        assert!(payload.contains(r#""rssi":"many""#), "{payload}");
+
+        assert_eq!(failure("<html>student</html>", &privacy).payload, "<20 bytes withheld, not json>");
+    }
+}
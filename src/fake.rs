@@ -0,0 +1,140 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use smartzone::{Client, Query, Record, SmartZoneApi, SmartZoneError, Zone, AP};
+
+/// An in-memory controller for driving collectors with hand-built records.
+#[derive(Default)]
+pub struct FakeSmartZone {
+    answers: Mutex<Answers>,
+    /// Zone ids in the order they were queried.
+    queried: Mutex<Vec<String>>,
+}
+
+/// What the fake controller returns.
+#[derive(Default)]
+struct Answers {
+    zones: Vec<Zone>,
+    /// Records by zone id, kept as json so any [`Record`] can be asked for.
+    aps: HashMap<String, Vec<Value>>,
+    clients: HashMap<String, Vec<Value>>,
+    /// Zone ids whose queries fail.
+    failing: HashSet<String>,
+    zones_fail: bool,
+}
+
+impl FakeSmartZone {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn zone(id: &str, name: &str) -> Zone {
+        Zone { id: id.to_string(), name: name.to_string() }
+    }
+
+    pub fn with_aps(mut self, zone: &Zone, aps: Vec<AP>) -> Self {
+        let answers = self.answers.get_mut().unwrap();
+        answers.add_zone(zone);
+        answers.aps.entry(zone.id.clone()).or_default().extend(aps.iter().map(to_value));
+        self
+    }
+
+    pub fn with_clients(mut self, zone: &Zone, clients: Vec<Client>) -> Self {
+        let answers = self.answers.get_mut().unwrap();
+        answers.add_zone(zone);
+        answers.clients.entry(zone.id.clone()).or_default().extend(clients.iter().map(to_value));
+        self
+    }
+
+    /// Queries for `zone` fail like a controller returning 500 would.
+    pub fn with_failing_zone(mut self, zone: &Zone) -> Self {
+        let answers = self.answers.get_mut().unwrap();
+        answers.add_zone(zone);
+        answers.failing.insert(zone.id.clone());
+        self
+    }
+
+    /// Listing the zones fails.
+    pub fn without_zones(mut self) -> Self {
+        self.answers.get_mut().unwrap().zones_fail = true;
+        self
+    }
+
+    /// Answers like `other` from now on, for a controller that changes
+    /// between polls.
+    pub fn answer_like(&self, other: FakeSmartZone) {
+        *self.answers.lock().unwrap() = other.answers.into_inner().unwrap();
+    }
+
+    pub fn queried(&self) -> Vec<String> {
+        self.queried.lock().unwrap().clone()
+    }
+
+    fn error(path: &str) -> SmartZoneError {
+        SmartZoneError::Status {
+            url: format!("fake://smartzone/{path}"),
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl Answers {
+    fn add_zone(&mut self, zone: &Zone) {
+        if !self.zones.iter().any(|z| z.id == zone.id) {
+            self.zones.push(zone.clone());
+        }
+    }
+}
+
+fn to_value(record: &impl Serialize) -> Value {
+    serde_json::to_value(record).unwrap()
+}
+
+impl SmartZoneApi for FakeSmartZone {
+    fn url(&self) -> &str {
+        "fake://smartzone"
+    }
+
+    async fn zones(&self) -> Result<Vec<Zone>, SmartZoneError> {
+        let answers = self.answers.lock().unwrap();
+        if answers.zones_fail {
+            return Err(Self::error("rkszones"));
+        }
+        Ok(answers.zones.clone())
+    }
+
+    /// Records are returned whole, like from a controller that ignores `attributes`.
+    async fn query_zone<T: Record + Send>(
+        &self,
+        zone: &Zone,
+        query: Query,
+        _attributes: &[&str],
+    ) -> Result<Vec<T>, SmartZoneError> {
+        self.queried.lock().unwrap().push(zone.id.clone());
+        let answers = self.answers.lock().unwrap();
+        if answers.failing.contains(&zone.id) {
+            return Err(Self::error("query"));
+        }
+        let records = match query {
+            Query::Aps => &answers.aps,
+            Query::Clients => &answers.clients,
+        };
+        records
+            .get(&zone.id)
+            .into_iter()
+            .flatten()
+            .map(|record| {
+                serde_json::from_value(record.clone()).map_err(|source| SmartZoneError::Decode {
+                    context: format!("fake {query:?}"),
+                    path: String::new(),
+                    source,
+                })
+            })
+            .collect()
+    }
+}
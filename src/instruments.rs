@@ -0,0 +1,601 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram, Meter, MeterProvider},
+    KeyValue,
+};
+use opentelemetry_sdk::{
+    metrics::{new_view, Aggregation, Instrument, SdkMeterProvider, Stream, View},
+    Resource,
+};
+use prometheus::{proto::MetricFamily, Registry};
+use regex::Regex;
+
+use smartzone::{
+    ap::{Radio, AP},
+    ApiObserver, Client, SmartZoneError, Zone,
+};
+
+use crate::{
+    collector::Controller,
+    diagnostics::{DecodeFailure, DecodeLog},
+    otlp::{Push, SharedReader},
+    privacy::PrivacyPolicy,
+};
+
+/// RSSI buckets in dBm, -67 is the usual minimum for voice and video.
+const RSSI_BUCKETS: &[f64] = &[-90.0, -85.0, -80.0, -75.0, -70.0, -67.0, -65.0, -60.0, -55.0, -50.0, -40.0, -30.0];
+/// SNR buckets in dB.
+const SNR_BUCKETS: &[f64] = &[5.0, 10.0, 15.0, 20.0, 25.0, 30.0, 35.0, 40.0, 50.0];
+
+/// Buckets in seconds for a single api request.
+pub const REQUEST_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+/// Buckets in seconds for polling every zone of a controller.
+pub const COLLECTION_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// A meter backed by its own registry, used for one collection cycle so
+/// that series of devices that are gone are not carried over.
+pub struct CycleMeter {
+    provider: SdkMeterProvider,
+    registry: Registry,
+    pub meter: Meter,
+    /// Set when metrics are also pushed over otlp.
+    push: Option<(SharedReader, Arc<Push>)>,
+}
+
+impl CycleMeter {
+    /// `resource` only shows up in otlp pushes, prometheus gets it from the
+    /// main registry's `target_info`. Instruments named in `disabled` are
+    /// not recorded.
+    pub fn new(resource: Resource, disabled: &[String]) -> Self {
+        Self::with_push(resource, disabled, Push::installed())
+    }
+
+    pub fn with_push(resource: Resource, disabled: &[String], push: Option<Arc<Push>>) -> Self {
+        let registry = Registry::new();
+        // target_info and otel_scope_info come from the main registry
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .without_target_info()
+            .without_scope_info()
+            .build()
+            .unwrap();
+        let mut builder = SdkMeterProvider::builder()
+            .with_resource(resource)
+            .with_reader(exporter)
+            .with_view(cycle_view(disabled));
+        let push = push.map(|push| (SharedReader::default(), push));
+        if let Some((reader, _)) = &push {
+            builder = builder.with_reader(reader.clone());
+        }
+        let provider = builder.build();
+        let meter = provider.meter("smartzone");
+        Self { provider, registry, meter, push }
+    }
+
+    pub fn finish(self) -> Vec<MetricFamily> {
+        let families = self.registry.gather();
+        if let Some((reader, push)) = &self.push {
+            match reader.collect_now() {
+                Ok(metrics) => push.export(metrics),
+                Err(err) => eprintln!("Failed to collect metrics for otlp: {err}"),
+            }
+        }
+        if let Err(err) = self.provider.shutdown() {
+            eprintln!("Failed to shut down cycle meter: {err}");
+        }
+        families
+    }
+}
+
+/// The default buckets are for latencies and would put every RSSI in one bucket.
+pub fn histogram_view(name: &'static str, boundaries: &[f64]) -> Box<dyn View> {
+    new_view(
+        Instrument::new().name(name),
+        Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: boundaries.to_vec(),
+            record_min_max: false,
+        }),
+    )
+    .unwrap()
+}
+
+/// Drops the instruments in `disabled`, `*` and `?` as wildcards, and
+/// buckets the client histograms. One view, as the sdk would record an
+/// instrument matched by two views twice.
+fn cycle_view(disabled: &[String]) -> impl View {
+    let disabled: Vec<Regex> = disabled
+        .iter()
+        .map(|pattern| {
+            let pattern = regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", ".");
+            Regex::new(&format!("^{pattern}$")).unwrap()
+        })
+        .collect();
+    move |instrument: &Instrument| {
+        if disabled.iter().any(|d| d.is_match(&instrument.name)) {
+            return Some(Stream::new().aggregation(Aggregation::Drop));
+        }
+        let boundaries = match &*instrument.name {
+            "ap_client_rssi" => RSSI_BUCKETS,
+            "ap_client_snr" => SNR_BUCKETS,
+            _ => return None,
+        };
+        // Unlike `new_view`, a closure view has to carry the name over itself
+        let stream = Stream::new()
+            .name(instrument.name.clone())
+            .description(instrument.description.clone())
+            .unit(instrument.unit.clone());
+        Some(stream.aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: boundaries.to_vec(),
+            record_min_max: false,
+        }))
+    }
+}
+
+/// The exporter's own view of the smartzone api, recorded on the global meter
+/// and served on `/metrics` under `smartzone_exporter_`. Responses that fail
+/// to decode also go to the decode log.
+#[derive(Debug)]
+pub struct ApiMetrics {
+    url: KeyValue,
+    decode_log: Arc<DecodeLog>,
+    privacy: Arc<PrivacyPolicy>,
+    request_duration: Histogram<f64>,
+    responses: Counter<u64>,
+    pages: Counter<u64>,
+    records: Counter<u64>,
+    skipped: Counter<u64>,
+    decode_failures: Counter<u64>,
+    logins: Counter<u64>,
+    relogins: Counter<u64>,
+    truncated: Counter<u64>,
+}
+
+impl ApiMetrics {
+    pub fn new(url: &str, decode_log: Arc<DecodeLog>, privacy: Arc<PrivacyPolicy>) -> Self {
+        Self::with_meter(&global::meter("smartzone"), url, decode_log, privacy)
+    }
+
+    pub fn with_meter(meter: &Meter, url: &str, decode_log: Arc<DecodeLog>, privacy: Arc<PrivacyPolicy>) -> Self {
+        let url = KeyValue::new("Url", url.trim_end_matches('/').to_string());
+        let relogins = meter
+            .u64_counter("smartzone_exporter_relogins")
+            .with_description("Number of times the smartzone session expired and was renewed")
+            .init();
+        // Start at zero so the series exists before the first renewal
+        relogins.add(0, std::slice::from_ref(&url));
+        Self {
+            url,
+            decode_log,
+            privacy,
+            request_duration: meter
+                .f64_histogram("smartzone_exporter_api_request_duration_seconds")
+                .with_description("Time until the smartzone api answered a request")
+                .init(),
+            responses: meter
+                .u64_counter("smartzone_exporter_api_responses")
+                .with_description("Responses from the smartzone api by status code, error if there was none")
+                .init(),
+            pages: meter
+                .u64_counter("smartzone_exporter_pages_fetched")
+                .with_description("Query pages fetched from the smartzone api")
+                .init(),
+            records: meter
+                .u64_counter("smartzone_exporter_records_decoded")
+                .with_description("Records decoded from query pages")
+                .init(),
+            skipped: meter
+                .u64_counter("smartzone_exporter_records_skipped")
+                .with_description("Records left out because they could not be decoded")
+                .init(),
+            decode_failures: meter
+                .u64_counter("smartzone_exporter_decode_failures")
+                .with_description("Responses and records that could not be decoded, skipped records are also in smartzone_exporter_records_skipped")
+                .init(),
+            logins: meter
+                .u64_counter("smartzone_exporter_login_attempts")
+                .with_description("Attempts to log in to the smartzone api by result")
+                .init(),
+            relogins,
+            truncated: meter
+                .u64_counter("smartzone_exporter_truncated_queries")
+                .with_description("Queries that still reported more records after their totalCount, the rest is not exported")
+                .init(),
+        }
+    }
+
+    fn labels(&self, key: &'static str, value: &str) -> [KeyValue; 2] {
+        [self.url.clone(), KeyValue::new(key, value.to_string())]
+    }
+}
+
+impl ApiObserver for ApiMetrics {
+    fn request(&self, endpoint: &str, seconds: f64, status: Option<u16>) {
+        let labels = self.labels("Endpoint", endpoint);
+        self.request_duration.record(seconds, &labels);
+        let status = status.map_or_else(|| "error".to_string(), |s| s.to_string());
+        let mut labels = labels.to_vec();
+        labels.push(KeyValue::new("Status", status));
+        self.responses.add(1, &labels);
+    }
+
+    fn page(&self, query: &str, records: usize, skipped: usize) {
+        let labels = self.labels("Query", query);
+        self.pages.add(1, &labels);
+        self.records.add(records as u64, &labels);
+        self.skipped.add(skipped as u64, &labels);
+    }
+
+    fn decode_failure(&self, endpoint: &str, err: &SmartZoneError, payload: &str) {
+        eprintln!("Bad data from {}: {err}", self.url.value);
+        self.decode_failures.add(1, &self.labels("Endpoint", endpoint));
+        self.decode_log.push(DecodeFailure::new(&self.url.value.as_str(), err, payload, &self.privacy));
+    }
+
+    fn login(&self, success: bool) {
+        self.logins.add(1, &self.labels("Result", if success { "success" } else { "failure" }));
+    }
+
+    fn certificate_skipped(&self, err: &str) {
+        eprintln!("Failed to load a system certificate: {err}");
+    }
+
+    fn truncated(&self, query: &str, total_count: usize) {
+        eprintln!(
+            "{} reported more {query} records after the {total_count} in totalCount, ignoring the rest",
+            self.url.value
+        );
+        self.truncated.add(1, &self.labels("Query", query));
+    }
+
+    fn relogin(&self) {
+        println!("Smartzone session at {} expired, logged in again", self.url.value);
+        self.relogins.add(1, std::slice::from_ref(&self.url));
+    }
+}
+
+/// Combines families of the same name from several collectors, the text
+/// format does not allow a metric name to appear twice.
+pub fn merge(families: impl IntoIterator<Item = MetricFamily>) -> Vec<MetricFamily> {
+    let mut merged: BTreeMap<String, MetricFamily> = BTreeMap::new();
+    for mut family in families {
+        match merged.get_mut(family.get_name()) {
+            Some(existing) => existing.mut_metric().extend(family.take_metric()),
+            None => {
+                merged.insert(family.get_name().to_string(), family);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut buffer = String::new();
+    let encoder = prometheus::TextEncoder::new();
+    encoder.encode_utf8(families, &mut buffer).unwrap();
+    buffer
+}
+
+/// `Mac`, `Hostname`, `IP` and `UserName` go through the controller's
+/// privacy policy, they may be hashed or left out entirely.
+pub fn record_client<A>(meter: &Meter, controller: &Controller<A>, zone: &Zone, client: &Client) {
+    let privacy = &controller.privacy;
+    let mut data = vec![
+        KeyValue::new("ApMac", client.ap_mac.clone()),
+        KeyValue::new("ApName", client.ap_name.clone()),
+        KeyValue::new("Zone", zone.name.clone()),
+        KeyValue::new("Controller", controller.name.clone()),
+    ];
+    data.extend(privacy.label(privacy.hostname, "Hostname", &client.hostname));
+    data.extend(privacy.label(privacy.mac, "Mac", &client.client_mac));
+    data.extend(privacy.label(privacy.user_name, "UserName", &client.user_name));
+
+    let mut data_verbose = vec![
+        KeyValue::new("OsType", client.os_type.clone()),
+        KeyValue::new("OsVendorType", client.os_vendor_type.clone()),
+        KeyValue::new("Vlan", client.vlan.to_string()),
+        KeyValue::new("ModelName", client.model_name.clone()),
+        KeyValue::new("SSID", client.ssid.clone()),
+        KeyValue::new("SessionStartTime", client.session_start_time.to_string()),
+    ];
+    data_verbose.extend(privacy.label(privacy.ip, "IP", &client.ip_address));
+    data_verbose.append(&mut data.clone());
+
+    // rx
+    let g = meter.u64_gauge("client_rx").with_description("rx bytes").init();
+    g.record(client.rx_bytes, &data_verbose);
+
+    // tx
+    let g = meter.u64_gauge("client_tx").with_description("tx bytes").init();
+    g.record(client.tx_bytes, &data_verbose);
+
+    // signal
+    let g = meter.i64_gauge("client_rssi").with_description("Received signal strength in dBm").init();
+    g.record(client.rssi, &data);
+    let g = meter.i64_gauge("client_snr").with_description("Signal to noise ratio in dB").init();
+    g.record(client.snr, &data);
+
+    // rates
+    let g = meter.u64_gauge("client_uplink_rate").with_description("Uplink rate in bits per second").init();
+    g.record(client.uplink_rate, &data);
+    let g = meter.u64_gauge("client_downlink_rate").with_description("Downlink rate in bits per second").init();
+    g.record(client.downlink_rate, &data);
+
+    // frames
+    let g = meter.u64_gauge("client_rx_frames").with_description("rx frames").init();
+    g.record(client.rx_frames, &data);
+    let g = meter.u64_gauge("client_tx_frames").with_description("tx frames").init();
+    g.record(client.tx_frames, &data);
+    let g = meter.u64_gauge("client_tx_drop_data_frames").with_description("Dropped tx data frames").init();
+    g.record(client.tx_drop_data_frames, &data);
+
+    // channel
+    let mut data_radio = data.clone();
+    data_radio.push(KeyValue::new("RadioType", client.radio_type.clone()));
+    let g = meter.u64_gauge("client_channel").with_description("Channel the client is connected on").init();
+    g.record(client.channel, &data_radio);
+
+    // Per AP distribution, keyed like the AP series so ap_info can be joined in
+    let data_ap = [
+        KeyValue::new("MAC", client.ap_mac.clone()),
+        KeyValue::new("Zone", zone.name.clone()),
+        KeyValue::new("Controller", controller.name.clone()),
+    ];
+    let h = meter.f64_histogram("ap_client_rssi").with_description("RSSI of the AP's clients in dBm").init();
+    h.record(client.rssi as f64, &data_ap);
+    let h = meter.f64_histogram("ap_client_snr").with_description("SNR of the AP's clients in dB").init();
+    h.record(client.snr as f64, &data_ap);
+}
+
+/// Numeric AP series are keyed only by `MAC`, `Zone` and `Controller`, which
+/// do not change between polls. Everything else about the AP is on `ap_info`
+/// and can be joined in with `* on(MAC, Zone, Controller) group_left(...) ap_info`.
+pub fn record_ap<A>(meter: &Meter, controller: &Controller<A>, zone: &Zone, ap: &AP) {
+    let data = vec![
+        KeyValue::new("MAC", ap.ap_mac.clone()),
+        KeyValue::new("Zone", zone.name.clone()),
+        KeyValue::new("Controller", controller.name.clone()),
+    ];
+
+    let mut data_info = vec![
+        KeyValue::new("DeviceName", ap.device_name.clone()),
+        KeyValue::new("Model", ap.model.clone()),
+        KeyValue::new("Serial", ap.serial.clone()),
+        KeyValue::new("Firmware", ap.firmware_version.clone()),
+        KeyValue::new("ApGroup", ap.ap_group_name.clone()),
+        KeyValue::new("Description", ap.description.clone()),
+        KeyValue::new("Location", ap.location.clone()),
+        KeyValue::new("IP", ap.ip.clone()),
+        KeyValue::new("Status", ap.status.clone()),
+    ];
+    data_info.append(&mut data.clone());
+
+    // Info
+    let info = meter.u64_gauge("ap_info").with_description("Static attributes of the AP, always 1").init();
+    info.record(1, &data_info);
+
+    // LastSeen, the controller reports it in milliseconds
+    let seen = meter
+        .f64_gauge("ap_last_seen_timestamp_seconds")
+        .with_description("Unix time the controller last heard from the AP")
+        .init();
+    seen.record(ap.last_seen as f64 / 1000.0, &data);
+
+    // Uptime
+    let uptime = meter.u64_gauge("ap_uptime").with_description("AP's uptime in seconds").init();
+    uptime.record(ap.uptime, &data);
+
+    // TX
+    let tx = meter.u64_gauge("ap_tx").with_description("AP's transmitted traffic").init();
+    tx.record(ap.tx, &data);
+
+    // RX
+    let rx = meter.u64_gauge("ap_rx").with_description("AP's received traffic").init();
+    rx.record(ap.rx, &data);
+
+    // TotalClients
+    let clients = meter.u64_gauge("ap_clients").with_description("Total number of clients connected to this AP").init();
+    clients.record(ap.num_clients, &data);
+
+    // alerts
+    let alerts = meter.u64_gauge("ap_alerts").with_description("Total number of alerts").init();
+    alerts.record(ap.alerts, &data);
+
+    // connection failures
+    let fail = meter.f64_gauge("ap_failures").init();
+    fail.record(ap.connection_failures, &data);
+
+    for radio in ap.radios() {
+        record_radio(meter, &radio, &data);
+    }
+}
+
+/// Per band values share a metric name and are told apart by the `Band` label.
+fn record_radio(meter: &Meter, radio: &Radio, data: &[KeyValue]) {
+    let mut data = data.to_vec();
+    data.push(KeyValue::new("Band", radio.band.label()));
+
+    let g = meter.i64_gauge("ap_channel").with_description("Channel the radio is on").init();
+    g.record(radio.channel, &data);
+    let g = meter.i64_gauge("ap_noise_floor").with_description("Noise floor in dBm").init();
+    g.record(radio.noise, &data);
+    let g = meter.u64_gauge("ap_airtime").with_description("Airtime utilization in percent").init();
+    g.record(radio.airtime, &data);
+    let g = meter.u64_gauge("ap_latency").with_description("Latency reported by the radio").init();
+    g.record(radio.latency, &data);
+    let g = meter.u64_gauge("ap_capacity").with_description("Estimated capacity of the radio").init();
+    g.record(radio.capacity, &data);
+    let g = meter.u64_gauge("ap_retries").with_description("Retry rate of the radio").init();
+    g.record(radio.retries, &data);
+    let g = meter.u64_gauge("ap_radio_tx").with_description("Radio's transmitted traffic").init();
+    g.record(radio.tx, &data);
+    let g = meter.u64_gauge("ap_radio_rx").with_description("Radio's received traffic").init();
+    g.record(radio.rx, &data);
+    let g = meter.u64_gauge("ap_radio_clients").with_description("Number of clients connected to the radio").init();
+    g.record(radio.clients, &data);
+    let g = meter.u64_gauge("ap_eirp").with_description("Effective isotropic radiated power in dBm").init();
+    g.record(radio.eirp, &data);
+    let g = meter.u64_gauge("ap_median_tx_mcs_rate").with_description("Median transmit MCS rate").init();
+    g.record(radio.median_tx_mcs_rate, &data);
+    let g = meter.u64_gauge("ap_median_rx_mcs_rate").with_description("Median receive MCS rate").init();
+    g.record(radio.median_rx_mcs_rate, &data);
+    let g = meter.i64_gauge("ap_tx_power_offset").with_description("Transmit power offset in dB").init();
+    g.record(radio.tx_power_offset, &data);
+    let g = meter.i64_gauge("ap_rx_desense").with_description("Receive desensitization in dB").init();
+    g.record(radio.rx_desense, &data);
+}
+
+/// How long a poll of every zone took, and when the last one without a
+/// failed zone finished.
+pub fn record_collection(meter: &Meter, labels: &[KeyValue], seconds: f64, success_at: Option<SystemTime>) {
+    let h = meter
+        .f64_histogram("smartzone_exporter_collection_duration_seconds")
+        .with_description("Time taken to poll every zone of a controller")
+        .init();
+    h.record(seconds, labels);
+    if let Some(at) = success_at {
+        let g = meter
+            .f64_gauge("smartzone_exporter_last_success_timestamp_seconds")
+            .with_description("Unix time of the last poll in which every zone succeeded")
+            .init();
+        g.record(at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(), labels);
+    }
+}
+
+/// Devices the controller returned more than once in a poll, only one of
+/// each is exported.
+pub fn record_duplicates(meter: &Meter, labels: &[KeyValue], discarded: u64) {
+    let c = meter
+        .u64_counter("smartzone_exporter_duplicates_discarded")
+        .with_description("Records dropped because the controller returned the same device more than once in a poll")
+        .init();
+    c.add(discarded, labels);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakeSmartZone, zones::ZoneFilter};
+
+    fn controller() -> Controller<FakeSmartZone> {
+        Controller {
+            name: "test".to_string(),
+            api: FakeSmartZone::new(),
+            privacy: Arc::default(),
+            zones: ZoneFilter::default(),
+            disabled_metrics: Arc::new([]),
+        }
+    }
+
+    fn client(mac: &str, rssi: i64, snr: i64) -> Client {
+        Client {
+            client_mac: mac.to_string(),
+            ap_mac: "AA:01".to_string(),
+            radio_type: "11ax".to_string(),
+            channel: 36,
+            rssi,
+            snr,
+            uplink_rate: 1_000,
+            downlink_rate: 2_000,
+            tx_frames: 7,
+            ..Default::default()
+        }
+    }
+
+    /// Records `clients` into a fresh cycle and returns the text exposition.
+    fn record(clients: &[Client], disabled: &[String]) -> String {
+        let cycle = CycleMeter::with_push(Resource::empty(), disabled, None);
+        let controller = controller();
+        let zone = Zone { id: "1".to_string(), name: "School".to_string() };
+        for client in clients {
+            record_client(&cycle.meter, &controller, &zone, client);
+        }
+        encode(&cycle.finish())
+    }
+
+    fn line<'a>(output: &'a str, prefix: &str) -> &'a str {
+        output.lines().find(|l| l.starts_with(prefix)).unwrap_or_else(|| panic!("no {prefix} in\n{output}"))
+    }
+
+    #[test]
+    fn records_client_series() {
+        let output = record(&[client("cc:01", -62, 30)], &[]);
+        assert!(line(&output, "client_rssi{").contains(r#"Mac="cc:01""#));
+        assert!(line(&output, "client_rssi{").ends_with(" -62"));
+        assert!(line(&output, "client_snr{").ends_with(" 30"));
+        assert!(line(&output, "client_uplink_rate{").ends_with(" 1000"));
+        assert!(line(&output, "client_downlink_rate{").ends_with(" 2000"));
+        assert!(line(&output, "client_tx_frames{").ends_with(" 7"));
+        assert!(line(&output, "client_channel{").contains(r#"RadioType="11ax""#));
+        assert!(output.contains("# HELP client_uplink_rate Uplink rate in bits per second"));
+    }
+
+    #[test]
+    fn buckets_clients_per_ap() {
+        let output = record(&[client("cc:01", -62, 30), client("cc:02", -82, 8)], &[]);
+        let labels = r#"Controller="test",MAC="AA:01",Zone="School""#;
+        assert!(line(&output, "ap_client_rssi_count{").ends_with(" 2"));
+        assert!(line(&output, &format!(r#"ap_client_rssi_bucket{{{labels},le="-85"}}"#)).ends_with(" 0"));
+        assert!(line(&output, &format!(r#"ap_client_rssi_bucket{{{labels},le="-80"}}"#)).ends_with(" 1"));
+        assert!(line(&output, &format!(r#"ap_client_rssi_bucket{{{labels},le="-60"}}"#)).ends_with(" 2"));
+        assert!(line(&output, &format!(r#"ap_client_snr_bucket{{{labels},le="10"}}"#)).ends_with(" 1"));
+        assert!(line(&output, "ap_client_snr_sum{").ends_with(" 38"));
+    }
+
+    #[test]
+    fn api_metrics_follow_the_observer() {
+        let cycle = CycleMeter::with_push(Resource::empty(), &[], None);
+        let log = Arc::new(DecodeLog::default());
+        let metrics = ApiMetrics::with_meter(&cycle.meter, "https://sz:8443/", log.clone(), Arc::new(PrivacyPolicy::default()));
+        metrics.request("rkszones", 0.2, Some(200));
+        metrics.request("query/ap", 0.4, None);
+        metrics.page("ap", 9, 1);
+        let err = serde_json::from_str::<u64>("\"many\"").unwrap_err();
+        let err = SmartZoneError::Decode { context: "query/ap page 1".to_string(), path: "list[1]".to_string(), source: err };
+        metrics.decode_failure("query/ap", &err, r#"{"numClients":"many"}"#);
+        metrics.login(true);
+        metrics.login(false);
+        metrics.relogin();
+        metrics.truncated("client", 10);
+        let output = encode(&cycle.finish());
+
+        let url = r#"Url="https://sz:8443""#;
+        let sample = |prefix: &str| line(&output, prefix).rsplit(' ').next().unwrap().to_string();
+        assert_eq!(sample(&format!(r#"smartzone_exporter_api_responses_total{{Endpoint="rkszones",Status="200",{url}}}"#)), "1");
+        assert_eq!(sample(&format!(r#"smartzone_exporter_api_responses_total{{Endpoint="query/ap",Status="error",{url}}}"#)), "1");
+        assert_eq!(sample("smartzone_exporter_api_request_duration_seconds_count{Endpoint=\"query/ap\""), "1");
+        assert_eq!(sample(&format!(r#"smartzone_exporter_pages_fetched_total{{Query="ap",{url}}}"#)), "1");
+        assert_eq!(sample("smartzone_exporter_records_decoded_total{"), "9");
+        assert_eq!(sample("smartzone_exporter_records_skipped_total{"), "1");
+        assert_eq!(sample(r#"smartzone_exporter_decode_failures_total{Endpoint="query/ap""#), "1");
+        assert_eq!(sample(r#"smartzone_exporter_login_attempts_total{Result="failure""#), "1");
+        assert_eq!(sample(r#"smartzone_exporter_login_attempts_total{Result="success""#), "1");
+        assert_eq!(sample(&format!("smartzone_exporter_relogins_total{{{url}}}")), "1");
+        assert_eq!(sample(r#"smartzone_exporter_truncated_queries_total{Query="client""#), "1");
+
+        let logged = log.recent();
+        assert_eq!(logged.len(), 1);
+        assert_eq!((logged[0].url.as_str(), logged[0].path.as_str()), ("https://sz:8443", "list[1]"));
+    }
+
+    #[test]
+    fn leaves_out_disabled_metrics() {
+        let output = record(&[client("cc:01", -62, 30)], &["ap_client_rssi".to_string(), "client_s?r".to_string()]);
+        assert!(!output.contains("ap_client_rssi"));
+        assert!(!output.contains("client_snr{"));
+        assert!(output.contains("ap_client_snr_count"));
+        assert!(output.contains("client_rssi{"));
+    }
+
+    #[test]
+    fn disabled_wildcards() {
+        let output = record(&[client("cc:01", -62, 30)], &["ap_client_*".to_string()]);
+        assert!(!output.contains("ap_client_"));
+        assert!(output.contains("client_rssi{"));
+    }
+}
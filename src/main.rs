@@ -1,200 +1,233 @@
 use std::sync::Arc;
-use opentelemetry::{
-    global,
-    metrics::Meter,
-    KeyValue,
-};
+use collector::{ActiveCollectors, Collector, Collectors, Controller};
+use config::Settings;
+use futures::future::join_all;
+use opentelemetry::{global, metrics::Meter};
 use prometheus::Registry;
 use rocket::{
-    get, routes, State,
+    get, http::ContentType, response::status::BadRequest, routes, State,
+};
+use smartzone::{
+    cassette::{CassetteMode, MAX_RECORDED},
+    Auth, Client, AP,
+};
+use zones::ZoneFilter;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+    task::JoinHandle,
 };
-use smartzone::{Auth, FilterContainer, Query};
-use tokio::sync::RwLock;
-
-mod smartzone;
-mod ap;
-mod client;
 
-struct Meters {
-    meter: Meter,
-}
+mod api;
+mod collector;
+mod config;
+mod diagnostics;
+#[cfg(test)]
+mod fake;
+mod instruments;
+mod otlp;
+mod privacy;
+mod zones;
 
 #[rocket::main]
 async fn main() {
-    let (meter_provider, registry) = init_meter_provider();
+    let otlp = match otlp::OtlpConfig::from_env() {
+        Ok(otlp) => otlp,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
+    let (meter_provider, registry) = match init_meter_provider(otlp.as_ref()) {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
     // Create a meter from the above MeterProvider.
     let meter = global::meter("smartzone");
-    // Create a Counter Instrument.
-    let mut session = smartzone::Auth::new(
-        dotenvy::var("RUST_USERNAME").expect("Set RUST_USERNAME"),
-        dotenvy::var("RUST_PASSWORD").expect("Set RUST_PASSWORD"),
-    ).await;
-    session.login().await;
-    let auth = Arc::new(session);
 
-    let dd = Meters {
-        meter,
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
     };
+    let decode_log = Arc::new(diagnostics::DecodeLog::default());
+    let active = Arc::new(ActiveCollectors::default());
+    let (running, collectors) = start(&settings, &meter, &decode_log).await;
+    active.replace(collectors);
+    let running = Arc::new(Mutex::new(running));
+    let _snapshot_age = collector::observe_snapshot_age(&meter, active.clone());
+    let _zone_success = collector::observe_zone_success(&meter, active.clone());
+    let reload = tokio::spawn(reload_on_hangup(meter.clone(), decode_log.clone(), active.clone(), running.clone()));
 
     let _ = rocket::build()
-        .mount("/", routes![metrics, clients])
+        .mount("/", routes![metrics, clients, decode_errors])
+        .mount("/", api::routes())
         .manage(registry)
-        .manage(RwLock::new(dd))
-        .manage(auth.clone())
+        .manage(active)
+        .manage(decode_log)
         .launch()
         .await;
 
-    auth.logout().await;
+    reload.abort();
+    running.lock().await.stop().await;
     meter_provider.shutdown().unwrap();
 }
 
-#[get("/clients")]
-async fn clients(
-    state: &State<Registry>,
-    auth: &State<Arc<Auth>>,
-    meters: &State<RwLock<Meters>>,
-) -> String {
-    // Go thru all the zones
-    for zone in &auth.get_zones().await {
-        // Get all the APs in the zone
-        let mut filter: FilterContainer = zone.into();
-        let mut left = true;
-        let mut all = Vec::new();
-        while left {
-            let mut clients = auth.query::<client::Client>(filter.clone(), Query::Clients).await;
-            left = clients.has_more;
-            filter.page += 1;
-            all.append(&mut clients.list);
-        }
-        for client in all {
-            let data = vec![
-                KeyValue::new("ApMac", client.ap_mac.clone()),
-                KeyValue::new("ApName", client.ap_name.clone()),
-                KeyValue::new("Hostname", client.hostname.clone()),
-                KeyValue::new("Mac", client.client_mac.clone()),
-                KeyValue::new("Zone", zone.name.clone()),
-            ];
-
-            let mut data_verbose = vec![
-                KeyValue::new("OsType", client.os_type.clone()),
-                KeyValue::new("OsVendorType", client.os_vendor_type.clone()),
-                KeyValue::new("IP", client.ip_address.clone()),
-                KeyValue::new("Vlan", client.vlan.to_string()),
-                KeyValue::new("ModelName", client.model_name.clone()),
-                KeyValue::new("SSID", client.ssid.clone()),
-                KeyValue::new("SessionStartTime", client.session_start_time.to_string()),
-            ];
-            data_verbose.append(&mut data.clone());
+/// The controllers and polling tasks built from one version of the config.
+#[derive(Default)]
+struct Running {
+    controllers: Vec<Arc<Controller>>,
+    tasks: Vec<JoinHandle<()>>,
+}
 
-            let lock = meters.write().await;
-            let meter = &lock.meter;
+impl Running {
+    async fn stop(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        for c in self.controllers.drain(..) {
+            match c.api.logout().await {
+                Ok(()) => println!("Logged out of {}", c.name),
+                Err(err) => eprintln!("Failed to logout of {}: {err}", c.name),
+            }
+        }
+    }
+}
 
-            // rx
-            let g = meter.u64_gauge("client_rx").with_description("rx bytes").init();
-            g.record(client.rx_bytes, &data_verbose);
+/// Sets up every controller and starts polling them. A controller that can
+/// not be set up, e.g. because its CA file is missing, is logged and left
+/// out, the others are started anyway.
+async fn start(settings: &Settings, meter: &Meter, decode_log: &Arc<diagnostics::DecodeLog>) -> (Running, Collectors) {
+    let privacy = Arc::new(settings.privacy.clone());
+    let disabled_metrics: Arc<[String]> = settings.disabled_metrics.clone().into();
+    let mut running = Running::default();
+    for config in &settings.controllers {
+        if let Some(CassetteMode::Record(dir)) = &config.cassette {
+            eprintln!(
+                "WARNING: recording the traffic of {} to {}. The responses are written as they are, with client MACs, hostnames and IPs. Recording stops once the directory holds {MAX_RECORDED} requests.",
+                config.name,
+                dir.display()
+            );
+        }
+        let api = match Auth::builder(&config.url, &config.username, &config.password)
+            .tls(config.tls.clone())
+            .api_version(config.api_version)
+            .paging(config.page_size, config.page_concurrency)
+            .lenient(config.lenient)
+            .time_zone(&config.time_zone)
+            .cassette(config.cassette.clone())
+            .observer(Arc::new(instruments::ApiMetrics::new(&config.url, decode_log.clone(), privacy.clone())))
+            .build()
+        {
+            Ok(api) => api,
+            Err(err) => {
+                eprintln!("Failed to set up controller {}, not polling it: {err}", config.name);
+                continue;
+            }
+        };
+        running.controllers.push(Arc::new(Controller {
+            name: config.name.clone(),
+            api,
+            privacy: privacy.clone(),
+            zones: config.zones.clone(),
+            disabled_metrics: disabled_metrics.clone(),
+        }));
+    }
+    // A failed login here is retried on the first poll
+    join_all(running.controllers.iter().map(|c| async move {
+        if let Err(err) = c.api.login().await {
+            eprintln!("Failed to login to {}: {err}", c.name);
+        }
+    }))
+    .await;
 
-            // tx
-            let g = meter.u64_gauge("client_tx").with_description("tx bytes").init();
-            g.record(client.tx_bytes, &data_verbose);
+    let mut collectors = Collectors::default();
+    for controller in &running.controllers {
+        let aps = Collector::<AP>::new(controller.clone(), settings.ap_interval, settings.grace);
+        let clients = Collector::<Client>::new(controller.clone(), settings.client_interval, settings.grace);
+        running.tasks.push(aps.spawn(meter.clone()));
+        running.tasks.push(clients.spawn(meter.clone()));
+        collectors.aps.push(aps);
+        collectors.clients.push(clients);
+    }
+    (running, collectors)
+}
 
+/// Loads the config again on SIGHUP and swaps in new collectors without
+/// restarting the http server. A config that does not load keeps the
+/// current one running.
+async fn reload_on_hangup(
+    meter: Meter,
+    decode_log: Arc<diagnostics::DecodeLog>,
+    active: Arc<ActiveCollectors>,
+    running: Arc<Mutex<Running>>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            eprintln!("Failed to listen for SIGHUP, the config can not be reloaded: {err}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        println!("Reloading config");
+        match Settings::load() {
+            Ok(settings) => {
+                let (new, collectors) = start(&settings, &meter, &decode_log).await;
+                let mut running = running.lock().await;
+                let mut old = std::mem::replace(&mut *running, new);
+                active.replace(collectors);
+                old.stop().await;
+                println!("Reloaded config");
+            }
+            Err(err) => eprintln!("Keeping the current config: {err}"),
         }
     }
-    let mut buffer = String::new();
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = state.gather();
-    encoder.encode_utf8(&metric_families, &mut buffer).unwrap();
-    buffer
+}
+
+// Both endpoints only encode what the collectors last recorded, the
+// controllers are polled in the background.
+#[get("/clients?<zone>")]
+async fn clients(collectors: &State<Arc<ActiveCollectors>>, zone: Vec<String>) -> Result<String, BadRequest<String>> {
+    let filter = ZoneFilter::parse(&zone, &[]).map_err(BadRequest)?;
+    let collectors = collectors.get();
+    let families = collectors.clients.iter().flat_map(|c| c.families_in(&filter));
+    Ok(instruments::encode(&instruments::merge(families)))
+}
+
+// The most recent responses that failed to decode, newest first, with the
+// path to the field that broke and the record or response it was in.
+#[get("/debug/decode-errors")]
+async fn decode_errors(log: &State<Arc<diagnostics::DecodeLog>>) -> (ContentType, String) {
+    (ContentType::JSON, serde_json::to_string_pretty(&log.recent()).unwrap())
 }
 
 // "/metrics" Is where prometheus expects to gather metrics at.
 // If you change this make sure your prometheus config reflects the change.
-#[get("/metrics")]
+// `?zone=` narrows the AP series down, the exporter's own metrics are always
+// included.
+#[get("/metrics?<zone>")]
 async fn metrics(
     state: &State<Registry>,
-    auth: &State<Arc<Auth>>,
-    meters: &State<RwLock<Meters>>,
-) -> String {
-    // Go thru all the zones
-    for zone in &auth.get_zones().await {
-        // Get all the APs in the zone
-        let mut filter: FilterContainer = zone.into();
-        let mut left = true;
-        let mut all_aps = Vec::new();
-        while left {
-            // let mut aps = auth.query_aps(filter.clone()).await;
-            let mut aps = auth.query::<ap::AP>(filter.clone(), Query::Aps).await;
-            left = aps.has_more;
-            filter.page += 1;
-            all_aps.append(&mut aps.list);
-        }
-
-        // Set metrics for each ap
-        for ap in &all_aps{
-            
-            let data = vec![
-                    KeyValue::new("DeviceName", ap.device_name.clone()),
-                    KeyValue::new("MAC", ap.ap_mac.clone()),
-                    KeyValue::new("IP", ap.ip.clone()),
-                    KeyValue::new("Zone", zone.name.clone()),
-                ];
-
-            let mut data_verbose = vec![
-                    KeyValue::new("Status", ap.status.clone()),
-                    KeyValue::new("LastSeen", ap.last_seen.to_string()),
-                    KeyValue::new("Model", ap.model.to_string()),
-            ];
-            data_verbose.append(&mut data.clone());
-
-            let lock = meters.write().await;
-            let meter = &lock.meter;
-
-            // Uptime
-            let tx = meter.u64_gauge("ap_uptime").with_description("AP's transmitted traffic").init();
-            tx.record(ap.uptime, &data_verbose);
-
-            // TX
-            let tx = meter.u64_gauge("ap_tx").with_description("AP's transmitted traffic").init();
-            tx.record(ap.tx, &data);
-
-            // RX
-            let rx = meter.u64_gauge("ap_rx").with_description("AP's received traffic").init();
-            rx.record(ap.rx, &data);
-
-            // TotalClients
-            let clients = meter.u64_gauge("ap_clients").with_description("Total number of clients connected to this AP").init();
-            clients.record(ap.num_clients, &data);
-
-            // alerts
-            let alerts = meter.u64_gauge("ap_alerts").with_description("Total number of alerts").init();
-            alerts.record(ap.alerts, &data);
-
-            // airtime utilization flagged
-            let flags = meter.u64_gauge("ap_airtime_24g").init();
-            flags.record(ap.airtime_24g, &data);
-            let flags = meter.u64_gauge("ap_airtime_5g").init();
-            flags.record(ap.airtime_5g, &data);
-
-            // connection failures
-            let fail = meter.f64_gauge("ap_failures").init();
-            fail.record(ap.connection_failures as f64, &data);
-
-            // latency flagged
-            let flags = meter.u64_gauge("ap_latency_5g").init();
-            flags.record(ap.latency50_g, &data);
-            let flags = meter.u64_gauge("ap_latency_24g").init();
-            flags.record(ap.latency24g, &data);
-
-        }
-    }
-
-    let mut buffer = String::new();
-    let encoder = prometheus::TextEncoder::new();
-    let metric_families = state.gather();
-    encoder.encode_utf8(&metric_families, &mut buffer).unwrap();
-    buffer
+    collectors: &State<Arc<ActiveCollectors>>,
+    zone: Vec<String>,
+) -> Result<String, BadRequest<String>> {
+    let filter = ZoneFilter::parse(&zone, &[]).map_err(BadRequest)?;
+    let collectors = collectors.get();
+    let mut metric_families = state.gather();
+    metric_families.extend(instruments::merge(collectors.aps.iter().flat_map(|c| c.families_in(&filter))));
+    Ok(instruments::encode(&metric_families))
 }
 
-fn init_meter_provider() -> (opentelemetry_sdk::metrics::SdkMeterProvider, Registry) {
+/// Serves metrics for prometheus and, if configured, also pushes them over otlp.
+fn init_meter_provider(
+    otlp: Option<&otlp::OtlpConfig>,
+) -> Result<(opentelemetry_sdk::metrics::SdkMeterProvider, Registry), String> {
     use opentelemetry_sdk::metrics::SdkMeterProvider;
 
     let registry = Registry::new();
@@ -202,13 +235,22 @@ fn init_meter_provider() -> (opentelemetry_sdk::metrics::SdkMeterProvider, Regis
         .with_registry(registry.clone())
         .build()
         .unwrap();
-    let provider = SdkMeterProvider::builder()
+    let mut builder = SdkMeterProvider::builder()
+        .with_resource(otlp::resource([]))
         .with_reader(exporter)
-        //        .with_resource(Resource::new([KeyValue::new(
-        //            "service.name",
-        //            "metrics-basic-example",
-        //        )]))
-        .build();
+        .with_view(instruments::histogram_view(
+            "smartzone_exporter_api_request_duration_seconds",
+            instruments::REQUEST_BUCKETS,
+        ))
+        .with_view(instruments::histogram_view(
+            "smartzone_exporter_collection_duration_seconds",
+            instruments::COLLECTION_BUCKETS,
+        ));
+    if let Some(config) = otlp {
+        builder = builder.with_reader(config.periodic_reader()?);
+        otlp::Push::new(config)?.install();
+    }
+    let provider = builder.build();
     global::set_meter_provider(provider.clone());
-    (provider, registry)
+    Ok((provider, registry))
 }
@@ -0,0 +1,384 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, OnceLock, Weak},
+    time::Duration,
+};
+
+use opentelemetry::{metrics::Result as MetricsResult, KeyValue};
+use opentelemetry_otlp::{MetricsExporter, MetricsExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::{
+    metrics::{
+        data::{ResourceMetrics, Temporality},
+        exporter::PushMetricsExporter,
+        reader::{
+            AggregationSelector, DefaultAggregationSelector, DefaultTemporalitySelector,
+            MetricReader, TemporalitySelector,
+        },
+        Aggregation, InstrumentKind, ManualReader, PeriodicReader, Pipeline,
+    },
+    runtime, Resource,
+};
+
+use crate::config;
+
+/// Wire format of the OTLP endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grpc" => Ok(Self::Grpc),
+            "http/protobuf" | "http" => Ok(Self::HttpProtobuf),
+            other => Err(format!(
+                "{other} is not an otlp protocol, use grpc or http/protobuf"
+            )),
+        }
+    }
+}
+
+/// Where to push metrics to, next to serving them for prometheus.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// `http://collector:4317` for grpc, `http://collector:4318` for http,
+    /// `/v1/metrics` is added if it is missing.
+    pub endpoint: String,
+    pub protocol: Protocol,
+    /// How often the exporter's own metrics are pushed. The AP and client
+    /// metrics are pushed after every poll.
+    pub interval: Duration,
+}
+
+impl OtlpConfig {
+    /// Reads `RUST_OTLP_ENDPOINT`, `RUST_OTLP_PROTOCOL` and `RUST_OTLP_INTERVAL`.
+    /// Pushing is off unless the endpoint is set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(endpoint) = dotenvy::var("RUST_OTLP_ENDPOINT")
+            .ok()
+            .filter(|e| !e.is_empty())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            endpoint,
+            protocol: dotenvy::var("RUST_OTLP_PROTOCOL")
+                .map_or(Ok(Protocol::Grpc), |p| p.parse())?,
+            interval: config::interval_from_env("RUST_OTLP_INTERVAL", Duration::from_secs(60))?,
+        }))
+    }
+
+    fn exporter(&self) -> Result<MetricsExporter, String> {
+        let builder: MetricsExporterBuilder = match self.protocol {
+            Protocol::Grpc => opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&self.endpoint)
+                .into(),
+            Protocol::HttpProtobuf => {
+                let endpoint = self.endpoint.trim_end_matches('/');
+                let endpoint = if endpoint.ends_with("/v1/metrics") {
+                    endpoint.to_string()
+                } else {
+                    format!("{endpoint}/v1/metrics")
+                };
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(endpoint)
+                    .into()
+            }
+        };
+        builder
+            .build_metrics_exporter(
+                Box::new(DefaultTemporalitySelector::new()),
+                Box::new(DefaultAggregationSelector::new()),
+            )
+            .map_err(|err| format!("Failed to set up otlp export to {}: {err}", self.endpoint))
+    }
+
+    /// Reader for the global provider, which pushes the exporter's own metrics.
+    pub fn periodic_reader(&self) -> Result<PeriodicReader, String> {
+        Ok(PeriodicReader::builder(self.exporter()?, runtime::Tokio)
+            .with_interval(self.interval)
+            .build())
+    }
+}
+
+/// Pushes the metrics of a finished collection cycle.
+///
+/// Every cycle records into its own provider, so there is no long lived
+/// reader to attach a periodic exporter to. Instead each cycle is collected
+/// once when it finishes and handed to this exporter.
+#[derive(Debug)]
+pub struct Push {
+    exporter: MetricsExporter,
+}
+
+static PUSH: OnceLock<Arc<Push>> = OnceLock::new();
+
+impl Push {
+    pub fn new(config: &OtlpConfig) -> Result<Self, String> {
+        Ok(Self {
+            exporter: config.exporter()?,
+        })
+    }
+
+    /// Makes every collection cycle push to `self`.
+    pub fn install(self) {
+        let _ = PUSH.set(Arc::new(self));
+    }
+
+    pub fn installed() -> Option<Arc<Push>> {
+        PUSH.get().cloned()
+    }
+
+    /// Exports in the background so a slow collector does not hold up the poll.
+    pub fn export(self: &Arc<Self>, mut metrics: ResourceMetrics) {
+        let push = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = push.exporter.export(&mut metrics).await {
+                eprintln!("Failed to push metrics over otlp: {err}");
+            }
+        });
+    }
+}
+
+/// Lets a [`ManualReader`] be collected after it was handed to a provider.
+#[derive(Debug, Clone, Default)]
+pub struct SharedReader(Arc<ManualReader>);
+
+impl SharedReader {
+    pub fn collect_now(&self) -> MetricsResult<ResourceMetrics> {
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        self.0.collect(&mut metrics)?;
+        Ok(metrics)
+    }
+}
+
+impl TemporalitySelector for SharedReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl AggregationSelector for SharedReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl MetricReader for SharedReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        self.0.shutdown()
+    }
+}
+
+/// `service.name` defaults to the crate name rather than `unknown_service`,
+/// `OTEL_SERVICE_NAME` and `OTEL_RESOURCE_ATTRIBUTES` still apply.
+pub fn resource(attributes: impl IntoIterator<Item = KeyValue>) -> Resource {
+    let name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+    Resource::default()
+        .merge(&Resource::new([KeyValue::new("service.name", name)]))
+        .merge(&Resource::new(attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruments::CycleMeter;
+    use opentelemetry_proto::tonic::{
+        collector::metrics::v1::{
+            metrics_service_server::{MetricsService, MetricsServiceServer},
+            ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+        },
+        common::v1::any_value::Value,
+    };
+    use prost::Message;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// Stands in for an OpenTelemetry Collector speaking grpc.
+    struct GrpcCollector(mpsc::UnboundedSender<ExportMetricsServiceRequest>);
+
+    #[tonic::async_trait]
+    impl MetricsService for GrpcCollector {
+        async fn export(
+            &self,
+            request: tonic::Request<ExportMetricsServiceRequest>,
+        ) -> Result<tonic::Response<ExportMetricsServiceResponse>, tonic::Status> {
+            let _ = self.0.send(request.into_inner());
+            Ok(tonic::Response::new(ExportMetricsServiceResponse::default()))
+        }
+    }
+
+    async fn grpc_collector() -> (String, mpsc::UnboundedReceiver<ExportMetricsServiceRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let conn = listener.accept().await.map(|(stream, _)| stream);
+            Some((conn, listener))
+        });
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(MetricsServiceServer::new(GrpcCollector(tx)))
+                .serve_with_incoming(incoming),
+        );
+        (endpoint, rx)
+    }
+
+    /// Stands in for an OpenTelemetry Collector speaking http/protobuf,
+    /// answering every request with an empty 200.
+    async fn http_collector() -> (
+        String,
+        mpsc::UnboundedReceiver<(String, ExportMetricsServiceRequest)>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let (head_len, body_len) = loop {
+                    let mut chunk = [0; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&buf[..pos]).to_ascii_lowercase();
+                        let len = head
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map_or(0, |l| l.trim().parse().unwrap());
+                        break (pos + 4, len);
+                    }
+                };
+                while buf.len() < head_len + body_len {
+                    let mut chunk = [0; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let head = String::from_utf8_lossy(&buf[..head_len]).to_string();
+                let path = head
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let request =
+                    ExportMetricsServiceRequest::decode(&buf[head_len..head_len + body_len])
+                        .unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tx.send((path, request));
+            }
+        });
+        (endpoint, rx)
+    }
+
+    /// Records one AP gauge the way a collection cycle does and finishes it.
+    fn push_cycle(push: Push) {
+        let cycle = CycleMeter::with_push(
+            resource([KeyValue::new(
+                "smartzone.controller.url",
+                "https://controller.invalid",
+            )]),
+            &[],
+            Some(Arc::new(push)),
+        );
+        let g = cycle.meter.u64_gauge("ap_tx").init();
+        g.record(42, &[KeyValue::new("MAC", "aa:bb")]);
+        cycle.finish();
+    }
+
+    fn metric_names(request: &ExportMetricsServiceRequest) -> Vec<String> {
+        request
+            .resource_metrics
+            .iter()
+            .flat_map(|r| &r.scope_metrics)
+            .flat_map(|s| &s.metrics)
+            .map(|m| m.name.clone())
+            .collect()
+    }
+
+    fn resource_attribute(request: &ExportMetricsServiceRequest, key: &str) -> Option<String> {
+        request
+            .resource_metrics
+            .iter()
+            .filter_map(|r| r.resource.as_ref())
+            .flat_map(|r| &r.attributes)
+            .find(|a| a.key == key)
+            .and_then(|a| match a.value.as_ref()?.value.as_ref()? {
+                Value::StringValue(s) => Some(s.clone()),
+                _ => None,
+            })
+    }
+
+    async fn received<T>(rx: &mut mpsc::UnboundedReceiver<T>) -> T {
+        tokio::time::timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .expect("nothing was pushed")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pushes_cycle_over_grpc() {
+        let (endpoint, mut rx) = grpc_collector().await;
+        let config = OtlpConfig {
+            endpoint,
+            protocol: Protocol::Grpc,
+            interval: Duration::from_secs(60),
+        };
+        push_cycle(Push::new(&config).unwrap());
+
+        let request = received(&mut rx).await;
+        assert_eq!(metric_names(&request), ["ap_tx"]);
+        assert_eq!(
+            resource_attribute(&request, "smartzone.controller.url").as_deref(),
+            Some("https://controller.invalid")
+        );
+        assert!(resource_attribute(&request, "service.name").is_some());
+    }
+
+    #[tokio::test]
+    async fn pushes_cycle_over_http() {
+        let (endpoint, mut rx) = http_collector().await;
+        let config = OtlpConfig {
+            endpoint,
+            protocol: Protocol::HttpProtobuf,
+            interval: Duration::from_secs(60),
+        };
+        push_cycle(Push::new(&config).unwrap());
+
+        let (path, request) = received(&mut rx).await;
+        assert_eq!(path, "/v1/metrics");
+        assert_eq!(metric_names(&request), ["ap_tx"]);
+        assert_eq!(
+            resource_attribute(&request, "smartzone.controller.url").as_deref(),
+            Some("https://controller.invalid")
+        );
+    }
+}
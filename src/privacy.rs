@@ -0,0 +1,262 @@
+use std::{fmt, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use opentelemetry::KeyValue;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::Sha256;
+
+/// What happens to a client label that can identify a person.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldPolicy {
+    Keep,
+    Drop,
+    /// Replaced with a keyed HMAC, so the same device keeps the same value
+    /// across polls but can not be looked up without the key.
+    Hash,
+}
+
+impl FromStr for FieldPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "drop" => Ok(Self::Drop),
+            "hash" => Ok(Self::Hash),
+            other => Err(format!("{other} is not one of keep, drop or hash")),
+        }
+    }
+}
+
+/// Decides how the identifying client labels are exported.
+///
+/// Leaves everything as it was by default, except the username which is
+/// only exported when asked for.
+#[derive(Clone)]
+pub struct PrivacyPolicy {
+    pub mac: FieldPolicy,
+    pub hostname: FieldPolicy,
+    pub ip: FieldPolicy,
+    pub user_name: FieldPolicy,
+    key: Option<Vec<u8>>,
+}
+
+impl Default for PrivacyPolicy {
+    fn default() -> Self {
+        Self {
+            mac: FieldPolicy::Keep,
+            hostname: FieldPolicy::Keep,
+            ip: FieldPolicy::Keep,
+            user_name: FieldPolicy::Drop,
+            key: None,
+        }
+    }
+}
+
+// The key must never end up in a log line.
+impl fmt::Debug for PrivacyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivacyPolicy")
+            .field("mac", &self.mac)
+            .field("hostname", &self.hostname)
+            .field("ip", &self.ip)
+            .field("user_name", &self.user_name)
+            .field("key", &self.key.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl PrivacyPolicy {
+    /// Reads `RUST_CLIENT_PRIVACY='mac=hash,hostname=drop,ip=drop'` and the
+    /// HMAC key from `RUST_CLIENT_PRIVACY_KEY` or `RUST_CLIENT_PRIVACY_KEY_FILE`.
+    pub fn from_env() -> Result<Self, String> {
+        let key = match (
+            dotenvy::var("RUST_CLIENT_PRIVACY_KEY").ok(),
+            dotenvy::var("RUST_CLIENT_PRIVACY_KEY_FILE").ok(),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err("Set only one of RUST_CLIENT_PRIVACY_KEY and RUST_CLIENT_PRIVACY_KEY_FILE".to_string())
+            }
+            (Some(key), None) => Some(key.into_bytes()),
+            (None, Some(path)) => Some(
+                std::fs::read(&path)
+                    .map_err(|err| format!("Failed to read RUST_CLIENT_PRIVACY_KEY_FILE {path}: {err}"))?
+                    .trim_ascii()
+                    .to_vec(),
+            ),
+            (None, None) => None,
+        };
+        match dotenvy::var("RUST_CLIENT_PRIVACY") {
+            Ok(spec) => Self::parse(&spec, key),
+            Err(_) => Self::parse("", key),
+        }
+    }
+
+    /// Keeps the default for every field and uses `key` for hashing.
+    pub fn with_key(key: Option<Vec<u8>>) -> Self {
+        Self { key, ..Self::default() }
+    }
+
+    pub fn parse(spec: &str, key: Option<Vec<u8>>) -> Result<Self, String> {
+        let mut policy = Self::with_key(key);
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (field, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("client privacy entry {entry} is not like mac=hash"))?;
+            let value = value.parse()?;
+            match field.trim().to_ascii_lowercase().as_str() {
+                "mac" => policy.mac = value,
+                "hostname" => policy.hostname = value,
+                "ip" => policy.ip = value,
+                "username" => policy.user_name = value,
+                other => return Err(format!("{other} is not one of mac, hostname, ip or username")),
+            }
+        }
+        policy.validate()
+    }
+
+    /// Makes sure there is a key if anything is hashed.
+    pub fn validate(self) -> Result<Self, String> {
+        let hashes = [self.mac, self.hostname, self.ip, self.user_name].contains(&FieldPolicy::Hash);
+        match &self.key {
+            Some(key) if key.is_empty() => Err("The client privacy key is empty".to_string()),
+            None if hashes => Err("Hashing client labels needs a privacy key".to_string()),
+            _ => Ok(self),
+        }
+    }
+
+    /// The label to export for `value`, or nothing if the field is dropped.
+    pub fn label(&self, field: FieldPolicy, name: &'static str, value: &str) -> Option<KeyValue> {
+        self.apply(field, value).map(|value| KeyValue::new(name, value))
+    }
+
+    /// What is exported for `value`, or nothing if the field is dropped.
+    pub fn apply(&self, field: FieldPolicy, value: &str) -> Option<String> {
+        match field {
+            FieldPolicy::Keep => Some(value.to_string()),
+            FieldPolicy::Drop => None,
+            FieldPolicy::Hash => Some(self.hash(value)),
+        }
+    }
+
+    /// Applies the policy to the client fields anywhere in a controller
+    /// response, a dropped field becomes `"<dropped>"`.
+    pub fn scrub(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, value) in fields.iter_mut() {
+                    match self.field(name) {
+                        Some(FieldPolicy::Keep) => {}
+                        Some(field) => {
+                            let text = match &*value {
+                                Value::String(text) => text.clone(),
+                                Value::Null => continue,
+                                other => other.to_string(),
+                            };
+                            let scrubbed = self.apply(field, &text).unwrap_or_else(|| "<dropped>".to_string());
+                            *value = Value::String(scrubbed);
+                        }
+                        None => self.scrub(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.scrub(item)),
+            _ => {}
+        }
+    }
+
+    /// Whether nothing is dropped or hashed.
+    pub fn keeps_everything(&self) -> bool {
+        [self.mac, self.hostname, self.ip, self.user_name].iter().all(|f| *f == FieldPolicy::Keep)
+    }
+
+    /// The policy for a client field as the controller names it.
+    fn field(&self, name: &str) -> Option<FieldPolicy> {
+        match name {
+            "clientMac" => Some(self.mac),
+            "hostname" => Some(self.hostname),
+            "ipAddress" | "ipv6Address" => Some(self.ip),
+            "userName" => Some(self.user_name),
+            _ => None,
+        }
+    }
+
+    /// First 16 bytes of HMAC-SHA256 as hex, plenty to keep devices apart.
+    fn hash(&self, value: &str) -> String {
+        // validate makes sure there is a key whenever a field is hashed
+        let key = self.key.as_deref().unwrap_or_default();
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes()[..16].iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(label: Option<KeyValue>) -> Option<String> {
+        label.map(|kv| kv.value.to_string())
+    }
+
+    #[test]
+    fn default_keeps_labels_but_not_username() {
+        let policy = PrivacyPolicy::parse("", None).unwrap();
+        assert_eq!(value(policy.label(policy.mac, "Mac", "aa:bb")), Some("aa:bb".to_string()));
+        assert_eq!(value(policy.label(policy.user_name, "UserName", "student")), None);
+    }
+
+    #[test]
+    fn hash_is_stable_and_depends_on_key() {
+        let policy = PrivacyPolicy::parse("mac=hash, hostname=drop", Some(b"secret".to_vec())).unwrap();
+        let first = value(policy.label(policy.mac, "Mac", "aa:bb")).unwrap();
+        let second = value(policy.label(policy.mac, "Mac", "aa:bb")).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 32);
+        assert_ne!(first, "aa:bb");
+        assert_eq!(value(policy.label(policy.hostname, "Hostname", "laptop")), None);
+
+        let other = PrivacyPolicy::parse("mac=hash", Some(b"other".to_vec())).unwrap();
+        assert_ne!(value(other.label(other.mac, "Mac", "aa:bb")).unwrap(), first);
+    }
+
+    #[test]
+    fn hash_needs_key() {
+        assert!(PrivacyPolicy::parse("mac=hash", None).is_err());
+        assert!(PrivacyPolicy::parse("mac=hash", Some(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn debug_hides_key() {
+        let policy = PrivacyPolicy::parse("mac=hash", Some(b"secret".to_vec())).unwrap();
+        assert!(!format!("{policy:?}").contains("secret"));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(PrivacyPolicy::parse("ssid=drop", None).is_err());
+        assert!(PrivacyPolicy::parse("mac=scramble", None).is_err());
+    }
+
+    #[test]
+    fn scrubs_client_fields_in_responses() {
+        let policy = PrivacyPolicy::parse("mac=hash, ip=drop", Some(b"secret".to_vec())).unwrap();
+        let mut response = serde_json::json!({
+            "totalCount": 1,
+            "list": [{"clientMac": "aa:bb", "hostname": "laptop", "ipAddress": "10.0.0.2", "ipv6Address": null, "userName": "student", "rssi": -60}],
+        });
+        policy.scrub(&mut response);
+        let client = &response["list"][0];
+        assert_eq!(client["clientMac"], policy.apply(FieldPolicy::Hash, "aa:bb").unwrap());
+        assert_eq!(client["hostname"], "laptop");
+        assert_eq!(client["ipAddress"], "<dropped>");
+        assert_eq!(client["ipv6Address"], Value::Null);
+        assert_eq!(client["userName"], "<dropped>");
+        assert_eq!(client["rssi"], -60);
+        assert_eq!(response["totalCount"], 1);
+        assert!(!policy.keeps_everything());
+        assert!(PrivacyPolicy::parse("username=keep", None).unwrap().keeps_everything());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::HashSet, str::FromStr};
+
+use prometheus::proto::MetricFamily;
+use regex::Regex;
+
+use smartzone::Zone;
+
+/// Picks zones by name, by id or by a regex over the name:
+/// `Main Campus`, `id:2b5f...` or `regex:^School`.
+#[derive(Debug, Clone)]
+pub enum ZoneMatcher {
+    Name(String),
+    Id(String),
+    Regex(Regex),
+}
+
+impl FromStr for ZoneMatcher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(id) = s.strip_prefix("id:") {
+            Ok(Self::Id(id.to_string()))
+        } else if let Some(pattern) = s.strip_prefix("regex:") {
+            // Anchored, so `regex:School` does not also pick `Old School Annex`
+            Regex::new(&format!("^(?:{pattern})$"))
+                .map(Self::Regex)
+                .map_err(|err| format!("invalid zone regex {pattern}: {err}"))
+        } else if s.is_empty() {
+            Err("zone name can not be empty".to_string())
+        } else {
+            Ok(Self::Name(s.to_string()))
+        }
+    }
+}
+
+impl ZoneMatcher {
+    pub fn matches(&self, zone: &Zone) -> bool {
+        match self {
+            Self::Name(name) => &zone.name == name,
+            Self::Id(id) => &zone.id == id,
+            Self::Regex(regex) => regex.is_match(&zone.name),
+        }
+    }
+}
+
+/// Which zones of a controller are polled. Zones that are left out are
+/// never queried, so they cost no api calls.
+#[derive(Debug, Clone, Default)]
+pub struct ZoneFilter {
+    /// Zones to poll, every zone if empty.
+    pub include: Vec<ZoneMatcher>,
+    /// Zones to skip, wins over `include`.
+    pub exclude: Vec<ZoneMatcher>,
+}
+
+impl ZoneFilter {
+    /// Parses the include and exclude lists, see [`ZoneMatcher`].
+    pub fn parse<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Result<Self, String> {
+        let parse = |list: &[S]| list.iter().map(|s| s.as_ref().parse()).collect::<Result<Vec<_>, _>>();
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    pub fn allows(&self, zone: &Zone) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|m| m.matches(zone)))
+            && !self.exclude.iter().any(|m| m.matches(zone))
+    }
+}
+
+/// Keeps the series whose `Zone` label is one of `names`. Series without a
+/// `Zone` label are kept.
+pub fn select(families: Vec<MetricFamily>, names: &HashSet<&str>) -> Vec<MetricFamily> {
+    families
+        .into_iter()
+        .filter_map(|mut family| {
+            let metrics = family
+                .take_metric()
+                .into_iter()
+                .filter(|metric| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .find(|label| label.get_name() == "Zone")
+                        .is_none_or(|label| names.contains(label.get_value()))
+                })
+                .collect::<Vec<_>>();
+            (!metrics.is_empty()).then(|| {
+                family.set_metric(metrics.into());
+                family
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(id: &str, name: &str) -> Zone {
+        Zone { id: id.to_string(), name: name.to_string() }
+    }
+
+    #[test]
+    fn matches_name_id_and_regex() {
+        let main = zone("1", "Main Campus");
+        assert!("Main Campus".parse::<ZoneMatcher>().unwrap().matches(&main));
+        assert!(!"Main".parse::<ZoneMatcher>().unwrap().matches(&main));
+        assert!("id:1".parse::<ZoneMatcher>().unwrap().matches(&main));
+        assert!("regex:Main.*".parse::<ZoneMatcher>().unwrap().matches(&main));
+        assert!(!"regex:Campus".parse::<ZoneMatcher>().unwrap().matches(&main));
+        assert!("regex:(".parse::<ZoneMatcher>().is_err());
+    }
+
+    #[test]
+    fn exclude_wins() {
+        let filter = ZoneFilter::parse(&["regex:School.*"], &["School Lab", "id:3"]).unwrap();
+        assert!(filter.allows(&zone("1", "School North")));
+        assert!(!filter.allows(&zone("2", "School Lab")));
+        assert!(!filter.allows(&zone("3", "School South")));
+        assert!(!filter.allows(&zone("4", "Default Zone")));
+        assert!(ZoneFilter::default().allows(&zone("4", "Default Zone")));
+    }
+}
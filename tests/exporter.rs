@@ -0,0 +1,251 @@
+//! Runs the exporter binary against the mock controller in `tests/mock` and
+//! checks what it serves.
+
+mod mock;
+
+use std::{
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use mock::MockSmartZone;
+
+/// The exporter running as its own process, killed when dropped.
+struct Exporter {
+    child: Child,
+    url: String,
+}
+
+impl Exporter {
+    fn start(controller: &str, env: &[(&str, &str)]) -> Self {
+        // Grab a free port for rocket, there is a small window for another
+        // process to take it before the exporter binds it.
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let child = Command::new(env!("CARGO_BIN_EXE_ruckus_metrics"))
+            .env_clear()
+            .current_dir(env!("CARGO_TARGET_TMPDIR"))
+            .env("ROCKET_ADDRESS", "127.0.0.1")
+            .env("ROCKET_PORT", port.to_string())
+            .env("RUST_URL", controller)
+            .env("RUST_USERNAME", "monitor")
+            .env("RUST_PASSWORD", "secret")
+            .env("RUST_AP_INTERVAL", "1")
+            .env("RUST_CLIENT_INTERVAL", "1")
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Self {
+            child,
+            url: format!("http://127.0.0.1:{port}"),
+        }
+    }
+
+    async fn get(&self, path: &str) -> Option<String> {
+        let response = reqwest::get(format!("{}{path}", self.url)).await.ok()?;
+        response.text().await.ok()
+    }
+
+    /// Fetches `path` until `ready` accepts it, the exporter needs a moment
+    /// to start and finish its first polls.
+    async fn wait_for(&self, path: &str, ready: impl Fn(&str) -> bool) -> String {
+        for _ in 0..100 {
+            if let Some(body) = self.get(path).await.filter(|body| ready(body)) {
+                return body;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        panic!("{path} never became ready, last got {:?}", self.get(path).await);
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The value of the first sample of `name` that has all of `labels`.
+fn sample(exposition: &str, name: &str, labels: &[&str]) -> Option<f64> {
+    exposition
+        .lines()
+        .filter(|line| line.strip_prefix(name).is_some_and(|rest| rest.starts_with(['{', ' '])))
+        .find(|line| labels.iter().all(|label| line.contains(label)))
+        .and_then(|line| line.rsplit(' ').next()?.parse().ok())
+}
+
+#[tokio::test]
+async fn exports_aps_and_clients_from_every_page() {
+    let running = MockSmartZone::start().await;
+    let exporter = Exporter::start(running.mock.url(), &[("RUST_PAGE_SIZE", "2")]);
+
+    let metrics = exporter.wait_for("/metrics", |m| m.contains("AP-Library-1") && m.contains("AP-Gym")).await;
+    for name in ["AP-Hall-1", "AP-Hall-2", "AP-Gym", "AP-Library-1"] {
+        assert!(metrics.contains(&format!(r#"DeviceName="{name}""#)), "{name} missing");
+    }
+    assert_eq!(sample(&metrics, "ap_clients", &[r#"MAC="AA:00:00:00:00:01""#, r#"Zone="Main Campus""#]), Some(2.0));
+    assert_eq!(sample(&metrics, "ap_tx", &[r#"MAC="AA:00:00:00:00:04""#, r#"Zone="Library""#]), Some(400.0));
+
+    // The mock also offers v12_0, which the exporter is not tested against
+    let requests = running.mock.requests();
+    assert!(requests.iter().all(|r| r.version == "v11_1"), "{requests:?}");
+
+    // Main Campus has three APs, which takes two pages of two
+    let pages: Vec<_> = requests
+        .into_iter()
+        .filter(|r| r.method == "POST" && r.endpoint == "query/ap")
+        .filter_map(|r| r.page)
+        .collect();
+    assert!(pages.contains(&2), "only requested pages {pages:?}");
+
+    let clients = exporter.wait_for("/clients", |c| c.contains("chromebook-4")).await;
+    for host in ["chromebook-17", "laptop-2", "phone-3", "chromebook-4"] {
+        assert!(clients.contains(&format!(r#"Hostname="{host}""#)), "{host} missing");
+    }
+    assert_eq!(sample(&clients, "client_rssi", &[r#"Mac="CC:00:00:00:00:01""#]), Some(-60.0));
+    // Usernames are dropped unless the privacy policy keeps them
+    assert!(!clients.contains("student"));
+}
+
+#[tokio::test]
+async fn skips_malformed_records() {
+    let running = MockSmartZone::start().await;
+    let exporter = Exporter::start(running.mock.url(), &[]);
+
+    let metrics = exporter.wait_for("/metrics", |m| m.contains("AP-Library-1")).await;
+    assert!(!metrics.contains("AA:00:00:00:00:99"));
+    assert!(sample(&metrics, "smartzone_exporter_records_skipped_total", &[r#"Query="ap""#]) >= Some(1.0));
+
+    let errors = exporter.wait_for("/debug/decode-errors", |e| e.contains("AP-Broken")).await;
+    assert!(errors.contains("list[1].numClients"), "{errors}");
+}
+
+#[tokio::test]
+async fn skips_malformed_clients() {
+    let running = MockSmartZone::start().await;
+    let exporter = Exporter::start(running.mock.url(), &[]);
+
+    // Clients are queried with `attributes`, which must not turn the null
+    // txBytes into a 0
+    let clients = exporter.wait_for("/clients", |c| c.contains("chromebook-4")).await;
+    assert!(!clients.contains("broken-client"));
+    let metrics = exporter.wait_for("/metrics", |m| m.contains(r#"Query="client""#)).await;
+    assert!(sample(&metrics, "smartzone_exporter_records_skipped_total", &[r#"Query="client""#]) >= Some(1.0));
+
+    let errors = exporter.wait_for("/debug/decode-errors", |e| e.contains("broken-client")).await;
+    assert!(errors.contains("list[1].txBytes"), "{errors}");
+}
+
+#[tokio::test]
+async fn renews_expired_session() {
+    let running = MockSmartZone::start().await;
+    let exporter = Exporter::start(running.mock.url(), &[]);
+
+    exporter.wait_for("/metrics", |m| m.contains("AP-Library-1")).await;
+    let logins = running.mock.logins();
+    running.mock.expire_sessions();
+
+    let metrics = exporter
+        .wait_for("/metrics", |m| sample(m, "smartzone_exporter_relogins_total", &[]) >= Some(1.0))
+        .await;
+    assert!(running.mock.logins() > logins);
+    assert!(metrics.contains("AP-Library-1"));
+    let clients = exporter.wait_for("/clients", |c| c.contains("chromebook-4")).await;
+    assert!(clients.contains("phone-3"));
+}
+
+#[tokio::test]
+async fn reloads_config_on_hangup() {
+    let running = MockSmartZone::start().await;
+    let config = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("reload.toml");
+    let write_config = |names: &[&str]| {
+        let controllers: String = names
+            .iter()
+            .map(|name| {
+                format!(
+                    "[[controllers]]\nname = \"{name}\"\nurl = \"{}\"\nusername = \"monitor\"\npassword = \"secret\"\n",
+                    running.mock.url()
+                )
+            })
+            .collect();
+        std::fs::write(&config, format!("ap_interval = 1\nclient_interval = 1\n{controllers}")).unwrap();
+    };
+    write_config(&["first"]);
+    let exporter = Exporter::start(running.mock.url(), &[("RUST_CONFIG_FILE", config.to_str().unwrap())]);
+    exporter
+        .wait_for("/metrics", |m| sample(m, "ap_clients", &[r#"Controller="first""#]).is_some())
+        .await;
+
+    write_config(&["second", "third"]);
+    let hangup = Command::new("kill").args(["-HUP", &exporter.child.id().to_string()]).status().unwrap();
+    assert!(hangup.success());
+
+    let metrics = exporter
+        .wait_for("/metrics", |m| {
+            ["second", "third"]
+                .iter()
+                .all(|name| sample(m, "ap_clients", &[&format!(r#"Controller="{name}""#)]).is_some())
+        })
+        .await;
+    // Only the self metrics of the process remember the old controller
+    let stale: Vec<_> = metrics
+        .lines()
+        .filter(|line| line.starts_with("ap_") && line.contains(r#"Controller="first""#))
+        .collect();
+    assert!(stale.is_empty(), "the old controller is still exported: {stale:?}");
+}
+
+#[tokio::test]
+async fn skips_controllers_that_can_not_be_set_up() {
+    let running = MockSmartZone::start().await;
+    let config = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("skip-broken.toml");
+    let url = running.mock.url();
+    std::fs::write(
+        &config,
+        format!(
+            "ap_interval = 1\nclient_interval = 1\n\
+             [[controllers]]\nname = \"broken\"\nurl = \"{url}\"\nusername = \"monitor\"\npassword = \"secret\"\n\
+             tls = {{ ca_file = \"/nonexistent/ca.pem\" }}\n\
+             [[controllers]]\nname = \"working\"\nurl = \"{url}\"\nusername = \"monitor\"\npassword = \"secret\"\n"
+        ),
+    )
+    .unwrap();
+    let exporter = Exporter::start(url, &[("RUST_CONFIG_FILE", config.to_str().unwrap())]);
+
+    let metrics = exporter
+        .wait_for("/metrics", |m| sample(m, "ap_clients", &[r#"Controller="working""#]).is_some())
+        .await;
+    assert!(sample(&metrics, "ap_clients", &[r#"Controller="broken""#]).is_none());
+}
+
+#[tokio::test]
+async fn replays_recorded_traffic() {
+    let cassette = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("cassette-replay");
+    let _ = std::fs::remove_dir_all(&cassette);
+    let dir = cassette.to_str().unwrap();
+
+    let running = MockSmartZone::start().await;
+    let url = running.mock.url().to_string();
+    let recording = Exporter::start(&url, &[("RUST_RECORD", dir)]);
+    recording.wait_for("/clients", |c| c.contains("chromebook-4")).await;
+    drop(recording);
+    drop(running);
+
+    let written = std::fs::read_dir(&cassette).unwrap().count();
+    assert!(written > 0);
+    let all: String = std::fs::read_dir(&cassette)
+        .unwrap()
+        .map(|file| std::fs::read_to_string(file.unwrap().path()).unwrap())
+        .collect();
+    assert!(!all.contains("secret"), "the password was recorded");
+    assert!(!all.contains("session-1"), "the session cookie was recorded");
+
+    // The controller is gone, everything comes from the cassette
+    let replaying = Exporter::start(&url, &[("RUST_REPLAY", dir)]);
+    let metrics = replaying.wait_for("/metrics", |m| m.contains("AP-Library-1")).await;
+    assert!(metrics.contains("AP-Gym"));
+    replaying.wait_for("/clients", |c| c.contains("chromebook-4")).await;
+}
@@ -0,0 +1,217 @@
+//! A stand-in smartzone controller serving the json in `tests/fixtures`.
+//!
+//! Only what the exporter uses is implemented: `apiInfo`, logging in and
+//! out, `rkszones` and the `query/ap` and `query/client` endpoints with zone
+//! filters, paging and `attributes`. Sessions can be expired to test
+//! renewing them.
+
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// A request the mock answered.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// The api version in the path, e.g. `v11_1`.
+    pub version: String,
+    /// Without `/wsg/api/public/<version>/`, e.g. `query/ap`.
+    pub endpoint: String,
+    /// The page asked for in a query body.
+    pub page: Option<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashSet<String>,
+    logins: usize,
+    requests: Vec<Request>,
+}
+
+pub struct MockSmartZone {
+    url: String,
+    api_info: Value,
+    zones: Value,
+    aps: Value,
+    clients: Value,
+    state: Mutex<State>,
+}
+
+/// Stops serving when dropped.
+pub struct Running {
+    pub mock: Arc<MockSmartZone>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn fixture(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+impl MockSmartZone {
+    /// Listens on a free local port.
+    pub async fn start() -> Running {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = Arc::new(MockSmartZone {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            api_info: fixture("api_info.json"),
+            zones: fixture("zones.json"),
+            aps: fixture("aps.json"),
+            clients: fixture("clients.json"),
+            state: Mutex::default(),
+        });
+        let server = mock.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let server = server.clone();
+                tokio::spawn(async move { server.serve(stream).await });
+            }
+        });
+        Running { mock, task }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Makes every session invalid, like the controller does after its
+    /// idle timeout.
+    pub fn expire_sessions(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+
+    pub fn logins(&self) -> usize {
+        self.state.lock().unwrap().logins
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    async fn serve(&self, mut stream: TcpStream) {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 4096];
+        let (head, body) = loop {
+            let n = stream.read(&mut chunk).await.unwrap_or(0);
+            if n == 0 {
+                return;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+            let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+            let length = header(&head, "content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+            if buffer.len() >= end + 4 + length {
+                break (head, String::from_utf8_lossy(&buffer[end + 4..end + 4 + length]).to_string());
+            }
+        };
+
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let method = request_line.next().unwrap_or_default().to_string();
+        let path = request_line.next().unwrap_or_default().to_string();
+        let session = header(&head, "cookie").and_then(|cookies| {
+            cookies
+                .split(';')
+                .find_map(|c| c.trim().strip_prefix("JSESSIONID=").map(String::from))
+        });
+        let (status, headers, body) = self.respond(&method, &path, session, &body);
+
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n{body}",
+            body.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+
+    fn respond(&self, method: &str, path: &str, session: Option<String>, body: &str) -> (&'static str, String, String) {
+        if path == "/wsg/api/public/apiInfo" {
+            return ("200 OK", String::new(), self.api_info.to_string());
+        }
+        // /wsg/api/public/v11_1/query/ap -> query/ap
+        let (version, endpoint) = path
+            .strip_prefix("/wsg/api/public/")
+            .and_then(|rest| rest.split_once('/'))
+            .map(|(version, endpoint)| (version.to_string(), endpoint.to_string()))
+            .unwrap_or_default();
+        let query: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(Request {
+            method: method.to_string(),
+            version,
+            endpoint: endpoint.clone(),
+            page: query["page"].as_u64(),
+        });
+
+        match (method, endpoint.as_str()) {
+            ("POST", "session") => {
+                state.logins += 1;
+                let token = format!("session-{}", state.logins);
+                state.sessions.insert(token.clone());
+                ("200 OK", format!("Set-Cookie: JSESSIONID={token}; Path=/wsg\r\n"), "{}".to_string())
+            }
+            _ if !session.is_some_and(|s| state.sessions.contains(&s)) => {
+                ("401 Unauthorized", String::new(), json!({ "message": "session expired" }).to_string())
+            }
+            ("DELETE", "session") => ("200 OK", String::new(), String::new()),
+            ("GET", "rkszones") => ("200 OK", String::new(), self.zones.to_string()),
+            ("POST", "query/ap") => ("200 OK", String::new(), page(&self.aps, &query).to_string()),
+            ("POST", "query/client") => ("200 OK", String::new(), page(&self.clients, &query).to_string()),
+            _ => ("404 Not Found", String::new(), String::new()),
+        }
+    }
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Answers a query with the records of the zone in its filter, a page at a
+/// time, leaving out the fields not in `attributes` if it was sent.
+fn page(records: &Value, query: &Value) -> Value {
+    let zone = query["filters"][0]["value"].as_str().unwrap_or_default();
+    let all = records[zone].as_array().cloned().unwrap_or_default();
+    let page = query["page"].as_u64().unwrap_or(1).max(1) as usize;
+    let limit = query["limit"].as_u64().unwrap_or(100).max(1) as usize;
+    let first = (page - 1) * limit;
+    let list: Vec<Value> = all.iter().skip(first).take(limit).map(|record| project(record, &query["attributes"])).collect();
+    json!({
+        "totalCount": all.len(),
+        "hasMore": first + list.len() < all.len(),
+        "firstIndex": first,
+        "list": list,
+    })
+}
+
+fn project(record: &Value, attributes: &Value) -> Value {
+    let (Some(attributes), Some(fields)) = (attributes.as_array(), record.as_object()) else {
+        return record.clone();
+    };
+    let fields = fields
+        .iter()
+        .filter(|(name, _)| attributes.iter().any(|a| a == name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    Value::Object(fields)
+}